@@ -0,0 +1,914 @@
+//! A Debug Adapter Protocol server for Kari
+//!
+//! Speaks the protocol over stdin/stdout, so it can be launched by any editor
+//! that supports DAP. The program is run on a separate thread, while the main
+//! thread handles the requests from the editor.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use serde_json::{json, Value};
+
+use kari::{
+    debugger::{Breakpoint, Breakpoints, Debugger, Pause, Resume},
+    source::Span,
+    value::{self, types::Typed as _, Value as _},
+    Interpreter,
+};
+
+fn main() {
+    let client = Client::new(Box::new(io::stdout()));
+    let mut session = Session::new(client.clone());
+
+    let stdin = io::stdin();
+    let mut input = BufReader::new(stdin.lock());
+
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(error) => {
+                eprintln!("Error reading message: {}", error);
+                exit(1);
+            }
+        };
+
+        if message["type"] == "request" {
+            session.handle(&message);
+        }
+    }
+}
+
+struct Session {
+    client: Client,
+
+    program: Option<String>,
+    stop_on_entry: bool,
+
+    breakpoints: Arc<Mutex<Breakpoints>>,
+    pause_requested: Arc<AtomicBool>,
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+    commands: Option<Sender<Resume>>,
+
+    /// Streams that aren't files, and are referred to by a source reference
+    source_references: Vec<String>,
+}
+
+impl Session {
+    fn new(client: Client) -> Self {
+        Self {
+            client,
+
+            program: None,
+            stop_on_entry: false,
+
+            breakpoints: Arc::new(Mutex::new(Breakpoints::new())),
+            pause_requested: Arc::new(AtomicBool::new(false)),
+            snapshot: Arc::new(Mutex::new(None)),
+            commands: None,
+
+            source_references: Vec::new(),
+        }
+    }
+
+    fn handle(&mut self, request: &Value) {
+        let args = &request["arguments"];
+
+        let result = match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                });
+                self.client.respond(request, capabilities);
+                self.client.event("initialized", json!({}));
+                return;
+            }
+            "launch" => self.launch(args),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                self.client.respond(request, json!({}));
+                self.start();
+                return;
+            }
+            "threads" => Ok(json!({
+                "threads": [{ "id": 1, "name": "main" }],
+            })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => self.scopes(args),
+            "variables" => self.variables(args),
+            "source" => self.source(args),
+            "continue" => return self.resume(request, Resume::Continue),
+            "next" => return self.resume(request, Resume::StepOver),
+            "stepIn" => return self.resume(request, Resume::StepInto),
+            "stepOut" => return self.resume(request, Resume::StepOut),
+            "pause" => {
+                self.pause_requested.store(true, Ordering::SeqCst);
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.client.respond(request, json!({}));
+                exit(0);
+            }
+            command => Err(format!("Unsupported request: {}", command)),
+        };
+
+        match result {
+            Ok(body) => self.client.respond(request, body),
+            Err(message) => self.client.respond_error(request, &message),
+        }
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"]
+            .as_str()
+            .ok_or("Missing `program` argument")?;
+
+        self.program = Some(program.into());
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+
+        Ok(json!({}))
+    }
+
+    fn start(&mut self) {
+        let program = match self.program.clone() {
+            Some(program) => program,
+            None => return,
+        };
+
+        let (commands, receiver) = channel();
+        self.commands = Some(commands);
+
+        let debugger = Remote {
+            client: self.client.clone(),
+            breakpoints: self.breakpoints.clone(),
+            pause_requested: self.pause_requested.clone(),
+            snapshot: self.snapshot.clone(),
+            commands: receiver,
+            stop_on_entry: self.stop_on_entry,
+            reason: if self.stop_on_entry {
+                Some("entry")
+            } else {
+                None
+            },
+        };
+        let client = self.client.clone();
+
+        thread::spawn(move || {
+            let exit_code = run(&program, debugger, client.clone());
+
+            client.event("exited", json!({ "exitCode": exit_code }));
+            client.event("terminated", json!({}));
+        });
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let stream_name = match args["source"]["path"].as_str() {
+            Some(path) => path.to_string(),
+            None => args["source"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        };
+
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        breakpoints.clear_stream(&stream_name);
+
+        let mut verified = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            if let Some(line) = breakpoint["line"].as_u64() {
+                breakpoints.add(Breakpoint::line(
+                    stream_name.clone(),
+                    line.saturating_sub(1) as usize,
+                ));
+                verified.push(json!({ "verified": true, "line": line }));
+            }
+        }
+
+        json!({ "breakpoints": verified })
+    }
+
+    fn set_function_breakpoints(&mut self, args: &Value) -> Value {
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+
+        // Function breakpoints are always replaced as a whole, but the
+        // `Breakpoints` also contain the ones for lines. Keep those.
+        let lines: Vec<_> = breakpoints
+            .iter()
+            .filter(|breakpoint| matches!(breakpoint, Breakpoint::Span(_)))
+            .cloned()
+            .collect();
+        breakpoints.clear();
+        for breakpoint in lines {
+            breakpoints.add(breakpoint);
+        }
+
+        let mut verified = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            if let Some(name) = breakpoint["name"].as_str() {
+                breakpoints.add(Breakpoint::Function(name.into()));
+                verified.push(json!({ "verified": true }));
+            }
+        }
+
+        json!({ "breakpoints": verified })
+    }
+
+    fn stack_trace(&mut self) -> Result<Value, String> {
+        let frames: Vec<_> = {
+            let snapshot = self.snapshot.lock().unwrap();
+            let snapshot = snapshot.as_ref().ok_or("Program is not paused")?;
+
+            snapshot
+                .frames
+                .iter()
+                .map(|frame| (frame.name.clone(), frame.span.clone()))
+                .collect()
+        };
+
+        let total_frames = frames.len();
+        let frames: Vec<_> = frames
+            .into_iter()
+            .enumerate()
+            .map(|(id, (name, span))| {
+                let mut frame = match &span {
+                    Some(span) => self.location(span),
+                    None => json!({ "line": 0, "column": 0 }),
+                };
+
+                frame["id"] = json!(id);
+                frame["name"] = json!(name);
+
+                frame
+            })
+            .collect();
+
+        Ok(json!({
+            "stackFrames": frames,
+            "totalFrames": total_frames,
+        }))
+    }
+
+    fn scopes(&mut self, args: &Value) -> Result<Value, String> {
+        let frame_id = args["frameId"]
+            .as_u64()
+            .ok_or("Missing `frameId` argument")?;
+
+        let snapshot = self.snapshot.lock().unwrap();
+        let snapshot = snapshot.as_ref().ok_or("Program is not paused")?;
+
+        let frame = snapshot
+            .frames
+            .get(frame_id as usize)
+            .ok_or("Unknown frame")?;
+
+        // All frames share the same stack. Only the frame's own variables
+        // depend on the frame.
+        Ok(json!({
+            "scopes": [
+                {
+                    "name": "Frame",
+                    "variablesReference": frame.variables + 1,
+                    "expensive": false,
+                },
+                {
+                    "name": "Stack",
+                    "variablesReference": STACK + 1,
+                    "expensive": false,
+                },
+            ],
+        }))
+    }
+
+    fn variables(&mut self, args: &Value) -> Result<Value, String> {
+        let reference = args["variablesReference"]
+            .as_u64()
+            .ok_or("Missing `variablesReference` argument")?;
+
+        let snapshot = self.snapshot.lock().unwrap();
+        let snapshot = snapshot.as_ref().ok_or("Program is not paused")?;
+
+        // References start at 1, as 0 means that there are no variables.
+        let container = (reference as usize)
+            .checked_sub(1)
+            .and_then(|i| snapshot.containers.get(i))
+            .ok_or("Unknown variables reference")?;
+
+        let variables: Vec<_> = container
+            .iter()
+            .map(|variable| {
+                json!({
+                    "name": variable.name,
+                    "value": variable.value.kind.to_string(),
+                    "type": variable.value.get_type().name(),
+                    "variablesReference": variable
+                        .children
+                        .map_or(0, |c| c + 1),
+                })
+            })
+            .collect();
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn source(&mut self, args: &Value) -> Result<Value, String> {
+        let reference = args["sourceReference"]
+            .as_u64()
+            .ok_or("Missing `sourceReference` argument")?;
+        // References start at 1, as 0 means that the source is a file.
+        let stream_name = (reference as usize)
+            .checked_sub(1)
+            .and_then(|i| self.source_references.get(i))
+            .ok_or("Unknown source reference")?;
+
+        let snapshot = self.snapshot.lock().unwrap();
+        let content = snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.sources.get(stream_name))
+            .ok_or("Source not available")?;
+
+        Ok(json!({ "content": content }))
+    }
+
+    fn resume(&mut self, request: &Value, resume: Resume) {
+        let commands = match &self.commands {
+            Some(commands) => commands,
+            None => {
+                self.client.respond_error(request, "Program is not running");
+                return;
+            }
+        };
+
+        *self.snapshot.lock().unwrap() = None;
+
+        // The response must go out before the program resumes. Otherwise, it
+        // could arrive after the event for the next pause.
+        self.client
+            .respond(request, json!({ "allThreadsContinued": true }));
+
+        // If the program has finished already, there's nothing to resume.
+        let _ = commands.send(resume);
+    }
+
+    /// Convert a span into the location fields of a DAP stack frame
+    fn location(&mut self, span: &Span) -> Value {
+        let source = if Path::new(&span.stream_name).is_file() {
+            json!({ "path": span.stream_name })
+        } else {
+            let reference = match self
+                .source_references
+                .iter()
+                .position(|name| name == &span.stream_name)
+            {
+                Some(i) => i + 1,
+                None => {
+                    self.source_references.push(span.stream_name.clone());
+                    self.source_references.len()
+                }
+            };
+
+            json!({
+                "name": span.stream_name,
+                "sourceReference": reference,
+            })
+        };
+
+        // DAP lines and columns start at 1 by default, ours start at 0. The
+        // end column is exclusive in DAP, but inclusive in our spans.
+        json!({
+            "source": source,
+            "line": span.start.line + 1,
+            "column": span.start.column + 1,
+            "endLine": span.end.line + 1,
+            "endColumn": span.end.column + 2,
+        })
+    }
+}
+
+/// Runs the program and returns its exit code
+fn run(program: &str, debugger: Remote, client: Client) -> i32 {
+    let file = match File::open(program) {
+        Ok(file) => file,
+        Err(error) => {
+            client.output(
+                "stderr",
+                &format!("Failed to open file {} ({})\n", program, error),
+            );
+            return 1;
+        }
+    };
+
+    let stdout = Box::new(OutputEvents {
+        category: "stdout",
+        client: client.clone(),
+    });
+    let stderr = Box::new(OutputEvents {
+        category: "stderr",
+        client: client.clone(),
+    });
+
+    let interpreter = match Interpreter::new(stdout, stderr)
+        .with_default_builtins()
+        .with_default_prelude(&mut ())
+    {
+        Ok(interpreter) => interpreter,
+        Err(error) => {
+            client.output(
+                "stderr",
+                &format!("Failed to load prelude: {}\n", error),
+            );
+            return 1;
+        }
+    };
+
+    let result = interpreter
+        .with_default_modules()
+        .with_debugger(Box::new(debugger))
+        .run(&mut (), program.into(), Box::new(file));
+
+    match result {
+        Ok(_) => 0,
+        Err(_) => 1,
+    }
+}
+
+/// The debugger that is attached to the interpreter
+///
+/// Lives on the thread that runs the program. When the program pauses, it
+/// publishes a snapshot of the program's state, notifies the client, and waits
+/// for a command from the main thread.
+struct Remote {
+    client: Client,
+    breakpoints: Arc<Mutex<Breakpoints>>,
+    pause_requested: Arc<AtomicBool>,
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+    commands: Receiver<Resume>,
+    stop_on_entry: bool,
+
+    /// The reason for the next pause, if it's not a step
+    reason: Option<&'static str>,
+}
+
+impl Debugger for Remote {
+    fn is_breakpoint(&mut self, word: &str, span: Option<&Span>) -> bool {
+        if self.pause_requested.swap(false, Ordering::SeqCst) {
+            self.reason = Some("pause");
+            return true;
+        }
+
+        if self.breakpoints.lock().unwrap().matches(word, span) {
+            self.reason = Some("breakpoint");
+            return true;
+        }
+
+        false
+    }
+
+    fn stop_on_entry(&self) -> bool {
+        self.stop_on_entry
+    }
+
+    fn pause(&mut self, pause: Pause) -> Resume {
+        *self.snapshot.lock().unwrap() = Some(Snapshot::new(&pause));
+
+        let reason = self.reason.take().unwrap_or("step");
+        self.client.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "threadId": 1,
+                "allThreadsStopped": true,
+            }),
+        );
+
+        // If the main thread is gone, there's nobody left to send us
+        // commands. Just finish the program.
+        self.commands.recv().unwrap_or(Resume::Continue)
+    }
+}
+
+/// The container that holds the values on the stack
+const STACK: usize = 0;
+
+/// The state of a paused program, as far as the client can see it
+struct Snapshot {
+    /// The stack frames, with the innermost frame first
+    frames: Vec<SnapshotFrame>,
+
+    /// Values that can be inspected as variables
+    ///
+    /// The first container is the stack, followed by one for each frame.
+    /// Values that are lists refer to the container that holds their items.
+    containers: Vec<Vec<Variable>>,
+
+    sources: HashMap<String, String>,
+}
+
+impl Snapshot {
+    fn new(pause: &Pause) -> Self {
+        // The innermost frame is at the word that is about to be evaluated.
+        // Every other frame is at the word that called the function of the
+        // frame before it. That word's name is the best name we have for the
+        // function.
+        let mut spans = vec![pause.span.cloned()];
+        let mut words = vec![pause.word.to_string()];
+        let mut names = Vec::new();
+
+        for frame in pause.frames.iter().rev() {
+            let name = frame
+                .span
+                .and_then(|span| word_at(span, pause.sources))
+                .unwrap_or(&frame.scope);
+
+            names.push(name.to_string());
+            words.push(name.to_string());
+            spans.push(frame.span.cloned());
+        }
+
+        // The outermost frame isn't in any function.
+        names.push("<program>".into());
+
+        let mut snapshot = Self {
+            frames: Vec::new(),
+            containers: Vec::new(),
+            sources: pause.sources.clone(),
+        };
+
+        let mut stack: Vec<_> = pause.stack.peek().cloned().collect();
+        stack.reverse();
+        let stack = stack
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| (format!("[{}]", i), value))
+            .collect();
+        snapshot.add_container(stack);
+
+        for ((name, word), span) in names.into_iter().zip(words).zip(spans) {
+            let variables = snapshot.add_container(vec![
+                ("function".into(), string(&name)),
+                ("word".into(), string(&word)),
+            ]);
+
+            snapshot.frames.push(SnapshotFrame {
+                name,
+                span,
+                variables,
+            });
+        }
+
+        snapshot
+    }
+
+    fn add_container(&mut self, values: Vec<(String, value::Any)>) -> usize {
+        let index = self.containers.len();
+        self.containers.push(Vec::new());
+
+        for (name, value) in values {
+            let children = match &value.kind {
+                value::Kind::List(list) => {
+                    let items = list
+                        .items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| (format!("[{}]", i), item.clone()))
+                        .collect();
+                    Some(self.add_container(items))
+                }
+                _ => None,
+            };
+
+            self.containers[index].push(Variable {
+                name,
+                value,
+                children,
+            });
+        }
+
+        index
+    }
+}
+
+struct SnapshotFrame {
+    name: String,
+    span: Option<Span>,
+
+    /// The container that holds the frame's variables
+    variables: usize,
+}
+
+struct Variable {
+    name: String,
+    value: value::Any,
+
+    /// The container that holds the value's items, if it's a list
+    children: Option<usize>,
+}
+
+fn string(s: &str) -> value::Any {
+    value::Any::new(value::Kind::String(s.into()), None)
+}
+
+fn word_at<'r>(
+    span: &Span,
+    sources: &'r HashMap<String, String>,
+) -> Option<&'r str> {
+    let source = sources.get(&span.stream_name)?;
+    let word = source.get(span.start.index..)?;
+    let end = word.find(char::is_whitespace).unwrap_or(word.len());
+
+    Some(&word[..end])
+}
+
+/// Sends messages to the client
+#[derive(Clone)]
+struct Client {
+    inner: Arc<Mutex<ClientInner>>,
+}
+
+struct ClientInner {
+    seq: u64,
+    stdout: Box<dyn Write + Send>,
+}
+
+impl Client {
+    fn new(stdout: Box<dyn Write + Send>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ClientInner { seq: 0, stdout })),
+        }
+    }
+
+    fn send(&self, mut message: Value) {
+        let mut inner = self.inner.lock().unwrap();
+
+        inner.seq += 1;
+        message["seq"] = json!(inner.seq);
+
+        let message = message.to_string();
+        let result = write!(
+            inner.stdout,
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message,
+        )
+        .and_then(|()| inner.stdout.flush());
+
+        if let Err(error) = result {
+            eprintln!("Error sending message: {}", error);
+            exit(1);
+        }
+    }
+
+    fn respond(&self, request: &Value, body: Value) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn respond_error(&self, request: &Value, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    fn event(&self, event: &str, body: Value) {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    fn output(&self, category: &str, output: &str) {
+        self.event(
+            "output",
+            json!({
+                "category": category,
+                "output": output,
+            }),
+        );
+    }
+}
+
+/// Forwards the program's output to the client
+struct OutputEvents {
+    category: &'static str,
+    client: Client,
+}
+
+impl Write for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.client
+            .output(self.category, &String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn read_message(input: &mut dyn BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(length) = line.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+
+    let content_length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length")
+    })?;
+
+    let mut content = vec![0; content_length];
+    input.read_exact(&mut content)?;
+
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::{json, Value};
+
+    use kari::{
+        debugger::{Frame, Pause},
+        stack::Stack,
+        value::{v, Value as _},
+    };
+
+    use super::{read_message, Client, Session, Snapshot};
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Harness {
+        session: Session,
+        output: Output,
+        seq: u64,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let output = Output::default();
+            let session = Session::new(Client::new(Box::new(output.clone())));
+
+            Self {
+                session,
+                output,
+                seq: 0,
+            }
+        }
+
+        /// Send a request through the wire format, returning all messages
+        /// that were sent back
+        fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            })
+            .to_string();
+            let request =
+                format!("Content-Length: {}\r\n\r\n{}", request.len(), request);
+
+            let request = read_message(&mut request.as_bytes()).unwrap();
+            self.session.handle(&request.unwrap());
+
+            let output = std::mem::take(&mut *self.output.0.lock().unwrap());
+            let mut output = output.as_slice();
+            let mut messages = Vec::new();
+            while let Some(message) = read_message(&mut output).unwrap() {
+                messages.push(message);
+            }
+
+            messages
+        }
+
+        fn pause(&mut self) {
+            let mut stack = Stack::new();
+            stack.push(v::Number::new(1, None));
+            let sources = HashMap::new();
+
+            let snapshot = Snapshot::new(&Pause {
+                word: "a",
+                span: None,
+                stack: &stack,
+                frames: vec![Frame {
+                    scope: "f".into(),
+                    span: None,
+                }],
+                sources: &sources,
+            });
+            *self.session.snapshot.lock().unwrap() = Some(snapshot);
+        }
+    }
+
+    #[test]
+    fn requests_should_get_a_response() {
+        let mut harness = Harness::new();
+
+        let messages = harness.request("initialize", json!({}));
+
+        assert_eq!(messages[0]["type"], "response");
+        assert_eq!(messages[0]["request_seq"], 1);
+        assert_eq!(messages[0]["command"], "initialize");
+        assert_eq!(messages[0]["success"], true);
+        assert_eq!(messages[1]["event"], "initialized");
+    }
+
+    #[test]
+    fn references_to_nothing_should_be_rejected() {
+        let mut harness = Harness::new();
+        harness.pause();
+
+        let messages =
+            harness.request("variables", json!({ "variablesReference": 0 }));
+        assert_eq!(messages[0]["success"], false);
+
+        let messages =
+            harness.request("source", json!({ "sourceReference": 0 }));
+        assert_eq!(messages[0]["success"], false);
+
+        let messages = harness.request("scopes", json!({ "frameId": 2 }));
+        assert_eq!(messages[0]["success"], false);
+    }
+
+    #[test]
+    fn scopes_should_refer_to_the_requested_frame() {
+        let mut harness = Harness::new();
+        harness.pause();
+
+        let variables = |harness: &mut Harness, frame: u64| {
+            let messages =
+                harness.request("scopes", json!({ "frameId": frame }));
+            let reference =
+                messages[0]["body"]["scopes"][0]["variablesReference"].clone();
+
+            let messages = harness.request(
+                "variables",
+                json!({ "variablesReference": reference }),
+            );
+            messages[0]["body"]["variables"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|variable| variable["value"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(variables(&mut harness, 0), ["f", "a"]);
+        assert_eq!(variables(&mut harness, 1), ["<program>", "f"]);
+    }
+}
@@ -1,16 +1,47 @@
 use std::{
     fs::File,
-    io::{stderr, stdin, stdout},
+    io::{self, stderr, stdin, stdout},
     process::exit,
 };
 
 use structopt::StructOpt;
 
-use kari::Interpreter;
+use kari::{debugger, observer, Interpreter};
 
 #[derive(StructOpt)]
 struct Options {
+    /// Step through the program, using commands entered on the console
+    #[structopt(long)]
+    debug: bool,
+
+    /// Write a trace of every evaluation step to stderr
+    #[structopt(long)]
+    trace: bool,
+
+    /// Write a summary of the time spent in each function to stderr
+    #[structopt(long)]
+    profile: bool,
+
+    /// Write the profile in the folded stacks format to this file, for use
+    /// with flamegraph tools (implies --profile)
+    #[structopt(long)]
+    profile_output: Option<String>,
+
+    /// Leave out all stack annotations, like `[: number :]`, instead of
+    /// checking them at runtime
+    #[structopt(long)]
+    strip_annotations: bool,
+
     path: Option<String>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Check a program for errors, without running it
+    Check { path: String },
 }
 
 fn main() {
@@ -19,39 +50,91 @@ fn main() {
 
     let options = Options::from_args();
 
+    let mut interpreter = Interpreter::new(stdout, stderr);
+    if options.strip_annotations {
+        interpreter = interpreter.with_annotations_stripped();
+    }
+
+    let mut interpreter = interpreter
+        .with_default_builtins()
+        .with_default_prelude(&mut ())
+        .unwrap_or_else(|error| {
+            println!("ERROR: Failed to load prelude: {}", error);
+            exit(1);
+        })
+        .with_default_modules();
+
+    if let Some(Command::Check { path }) = options.command {
+        let mut file = open(&path);
+        let errors = interpreter.check(path.into(), &mut file);
+        if !errors.is_empty() {
+            exit(1);
+        }
+        return;
+    }
+
+    if options.debug {
+        // Debugger commands are read from stdin. Without a path, the program
+        // is read from there too, one character at a time. Read commands
+        // without buffering, so they don't take input meant for the program.
+        let input = io::BufReader::with_capacity(1, stdin());
+
+        interpreter = interpreter.with_debugger(Box::new(
+            debugger::Console::new(input, io::stdout()),
+        ));
+    }
+
+    if options.trace {
+        interpreter = interpreter
+            .with_observer(Box::new(observer::Tracer::new(io::stderr())));
+    }
+
+    let profiler = if options.profile || options.profile_output.is_some() {
+        let profiler = observer::Profiler::new();
+        interpreter = interpreter.with_observer(Box::new(profiler.clone()));
+        Some(profiler)
+    } else {
+        None
+    };
+
     match options.path {
         Some(path) => {
-            let file =
-                File::open(&path).unwrap_or_else(|error| {
-                    print!(
-                        "\nERROR: Failed to open file {} ({})\n\n",
-                        path, error,
-                    );
-                    exit(1);
-                });
-
-            let _ = Interpreter::new(stdout, stderr)
-                .with_default_builtins()
-                .with_default_prelude(&mut ())
-                .unwrap_or_else(|error| {
-                    println!("ERROR: Failed to load prelude: {}", error);
-                    exit(1);
-                })
-                .with_default_modules()
-                .run(&mut (), path.into(), Box::new(file));
+            let file = open(&path);
+            let _ = interpreter.run(&mut (), path.into(), Box::new(file));
         }
         None => {
             let stdin = Box::new(stdin());
 
-            let _ = Interpreter::new(stdout, stderr)
-                .with_default_builtins()
-                .with_default_prelude(&mut ())
-                .unwrap_or_else(|error| {
-                    println!("ERROR: Failed to load prelude: {}", error);
-                    exit(1);
-                })
-                .with_default_modules()
-                .run(&mut (), "<stdin>".into(), stdin);
+            let _ = interpreter.run(&mut (), "<stdin>".into(), stdin);
         }
     }
+
+    if let Some(profiler) = profiler {
+        write_profile(&profiler, options.profile_output.as_deref())
+            .unwrap_or_else(|error| {
+                print!("\nERROR: Failed to write profile ({})\n\n", error);
+                exit(1);
+            });
+    }
+}
+
+fn open(path: &str) -> File {
+    File::open(path).unwrap_or_else(|error| {
+        print!("\nERROR: Failed to open file {} ({})\n\n", path, error);
+        exit(1);
+    })
+}
+
+fn write_profile(
+    profiler: &observer::Profiler,
+    output: Option<&str>,
+) -> io::Result<()> {
+    profiler.write_summary(&mut stderr())?;
+
+    if let Some(path) = output {
+        let mut file = File::create(path)?;
+        profiler.write_folded(&mut file)?;
+    }
+
+    Ok(())
 }
@@ -1,20 +1,205 @@
 use std::{
-    fs::File,
-    io::{stderr, stdout},
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, stderr, stdout, Write},
+    path::Path,
     process::exit,
+    rc::Rc,
+    time::{Duration, Instant},
 };
 
+use structopt::StructOpt;
 use termion::{color, style};
 use walkdir::WalkDir;
 
-use kari::Interpreter;
+use kari::{
+    observer::Coverage,
+    test_case::{Filter, TestResult},
+    Interpreter,
+};
+
+/// Runs Kari tests
+///
+/// A test is a Kari program that succeeds, if it runs without errors. A test
+/// can also expect a specific output, or expect to fail with a specific kind
+/// of error:
+///
+/// The expected output can be put into a file next to the test, with the same
+/// name and the extension `.out`, or into a comment section in the test
+/// itself:
+///
+///     # expect:
+///     # first line of output
+///     # second line of output
+///
+/// A test that is expected to fail names the kind of error it expects:
+///
+///     # expect-error: type
+///
+/// Tests can also register named test cases, using `test`. Each test case runs
+/// after the test itself has finished, and the test only passes, if all of its
+/// test cases do.
+#[derive(StructOpt)]
+struct Options {
+    /// Only run tests whose path contains this string, and test cases whose
+    /// name contains it
+    #[structopt(long)]
+    filter: Option<String>,
+
+    /// Print the source code of all streams, annotated with how often each
+    /// line was evaluated
+    #[structopt(long)]
+    coverage: bool,
+
+    /// Write the coverage of all streams to this file, in the lcov format
+    #[structopt(long)]
+    lcov: Option<String>,
+
+    /// Test files, or directories to search for test files (defaults to
+    /// `kr/tests`)
+    paths: Vec<String>,
+}
 
 fn main() {
+    let options = Options::from_args();
+
     println!();
 
-    let mut results = Vec::new();
+    let coverage = if options.coverage || options.lcov.is_some() {
+        Some(Coverage::new())
+    } else {
+        None
+    };
+
+    let mut template = Interpreter::new(Box::new(stdout()), Box::new(stderr()));
+    if let Some(coverage) = &coverage {
+        // Attach the coverage before the prelude is loaded, so it knows about
+        // the functions defined there.
+        template = template.with_observer(Box::new(coverage.clone()));
+    }
+
+    let template = template
+        .with_default_builtins()
+        .with_default_prelude(&mut ())
+        .unwrap_or_else(|error| {
+            println!("ERROR: Failed to load prelude: {}", error);
+            exit(1);
+        })
+        .with_default_modules();
+
+    let paths = if options.paths.is_empty() {
+        vec![String::from("kr/tests")]
+    } else {
+        options.paths.clone()
+    };
+
+    let mut tests = Vec::new();
+    for path in &paths {
+        find_tests(path, &mut tests);
+    }
+
+    let mut sources = template.sources().clone();
+    let mut failed = 0;
+    let mut filtered_out = 0;
+    let start = Instant::now();
 
-    for result in WalkDir::new("kr/tests") {
+    for path in &tests {
+        let source = fs::read_to_string(path).unwrap_or_else(|error| {
+            print!("\nERROR: Failed to read file {} ({})\n\n", path, error);
+            exit(1);
+        });
+
+        let stdout = Capture::default();
+        let stderr = Capture::default();
+
+        let mut interpreter =
+            template.fork(Box::new(stdout.clone()), Box::new(stderr.clone()));
+        if let Some(coverage) = &coverage {
+            interpreter = interpreter.with_observer(Box::new(coverage.clone()));
+        }
+
+        // A test whose path doesn't match the filter still has to run, as it
+        // might register test cases whose names do.
+        let filter = Filter::new(options.filter.as_deref());
+        let path_matches = filter.matches_path(path);
+
+        let test_start = Instant::now();
+        let result = interpreter.test_matching(
+            &mut (),
+            path.as_str().into(),
+            Box::new(io::Cursor::new(source.clone())),
+            |name| filter.matches_case(path, name),
+        );
+        let duration = test_start.elapsed();
+
+        let mut expectation = Expectation::read(path, &source);
+        if !path_matches {
+            if result.as_ref().map_or(true, |cases| cases.is_empty()) {
+                filtered_out += 1;
+                continue;
+            }
+
+            // The output of the test cases that were left out is missing.
+            expectation.output = None;
+        }
+
+        let failure = expectation.check(
+            result.as_ref().err().map(|error| error.kind.name()),
+            &stdout.contents(),
+        );
+
+        let test_cases = result.unwrap_or_default();
+        let success = failure.is_none()
+            && test_cases.iter().all(|test_case| test_case.result.is_ok());
+
+        print_result(success, path, duration);
+        for test_case in &test_cases {
+            print_test_case(path, test_case);
+        }
+
+        if !success {
+            failed += 1;
+
+            if let Some(failure) = failure {
+                println!("{}", failure);
+            }
+
+            let stderr = stderr.contents();
+            if !stderr.is_empty() {
+                println!("{}", stderr);
+            }
+        }
+
+        sources.insert(path.clone(), source);
+    }
+
+    println!();
+    println!(
+        "{} passed; {} failed; {} filtered out; finished in {:.2}s",
+        tests.len() - filtered_out - failed,
+        failed,
+        filtered_out,
+        start.elapsed().as_secs_f64(),
+    );
+    println!();
+
+    if let Some(coverage) = coverage {
+        write_coverage(&coverage, &sources, &options).unwrap_or_else(|error| {
+            println!("ERROR: Failed to write coverage ({})", error);
+            exit(1);
+        });
+    }
+
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+fn find_tests(path: &str, tests: &mut Vec<String>) {
+    let mut found = Vec::new();
+
+    for result in WalkDir::new(path) {
         let entry = result.unwrap_or_else(|error| {
             print!("ERROR: Error walking tests directory: {}", error,);
             if let Some(path) = error.path() {
@@ -36,48 +221,195 @@ fn main() {
             continue;
         }
 
-        let file = File::open(path).unwrap_or_else(|error| {
-            print!("\nERROR: Failed to open file {} ({})\n\n", path, error,);
-            exit(1);
-        });
+        found.push(path.to_owned());
+    }
 
-        let stdout = Box::new(stdout());
-        let stderr = Box::new(stderr());
+    found.sort();
+    tests.extend(found);
+}
 
-        let success = Interpreter::new(stdout, stderr)
-            .with_default_builtins()
-            .with_default_prelude(&mut ())
-            .unwrap_or_else(|error| {
-                println!("ERROR: Failed to load prelude: {}", error);
-                exit(1);
-            })
-            .with_default_modules()
-            .run(&mut (), path.into(), Box::new(file));
+fn print_result(success: bool, path: &str, duration: Duration) {
+    let (status, color) = if success {
+        ("   OK", color::Fg(color::LightGreen).to_string())
+    } else {
+        ("ERROR", color::Fg(color::Red).to_string())
+    };
 
-        results.push((success, path.to_owned()));
-    }
+    println!(
+        "    {}{}{}{}{} {} ({:.1} ms)",
+        style::Bold,
+        color,
+        status,
+        color::Fg(color::Reset),
+        style::Reset,
+        path,
+        duration.as_secs_f64() * 1000.0,
+    );
+}
 
-    for (success, path) in results {
-        if success.is_ok() {
+fn print_test_case(path: &str, test_case: &TestResult) {
+    match &test_case.result {
+        Ok(()) => {
             println!(
-                "       {}{}OK{}{} {}",
-                style::Bold,
+                "          {}ok{} {}",
                 color::Fg(color::LightGreen),
                 color::Fg(color::Reset),
-                style::Reset,
-                path,
+                test_case.name,
             );
-        } else {
-            println!(
-                "    {}{}ERROR{}{} {}",
-                style::Bold,
+        }
+        Err(error) => {
+            print!(
+                "      {}FAILED{} {}",
                 color::Fg(color::Red),
                 color::Fg(color::Reset),
-                style::Reset,
-                path,
+                test_case.name,
             );
+            // Errors often originate in some library function. The location
+            // in the test itself is more useful.
+            let span = error
+                .call_stack
+                .frames
+                .iter()
+                .rev()
+                .filter_map(|frame| frame.span.as_ref())
+                .find(|span| span.stream_name == path)
+                .or_else(|| error.span())
+                .or(test_case.span.as_ref());
+
+            if let Some(span) = span {
+                print!(
+                    " ({}:{}:{})",
+                    span.stream_name,
+                    span.start.line + 1,
+                    span.start.column + 1,
+                );
+            }
+            println!(": {}", error);
         }
     }
+}
+
+fn write_coverage(
+    coverage: &Coverage,
+    sources: &HashMap<String, String>,
+    options: &Options,
+) -> io::Result<()> {
+    if options.coverage {
+        coverage.write_annotated(sources, &mut stdout())?;
+    } else {
+        coverage.write_summary(sources, &mut stdout())?;
+    }
+
+    if let Some(path) = &options.lcov {
+        let mut file = File::create(path)?;
+        coverage.write_lcov(sources, &mut file)?;
+    }
 
     println!();
+
+    Ok(())
+}
+
+/// What a test expects to happen, if it runs
+#[derive(Default)]
+struct Expectation {
+    output: Option<String>,
+    error: Option<String>,
+}
+
+impl Expectation {
+    fn read(path: &str, source: &str) -> Self {
+        let mut expectation = Self::default();
+
+        let mut lines = source.lines().peekable();
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+
+            if let Some(error) = line.strip_prefix("# expect-error:") {
+                expectation.error = Some(error.trim().into());
+            }
+
+            if line == "# expect:" {
+                let mut output = String::new();
+
+                while let Some(line) =
+                    lines.next_if(|line| line.trim_start().starts_with('#'))
+                {
+                    let line = line.trim_start().trim_start_matches('#');
+                    let line = line.strip_prefix(' ').unwrap_or(line);
+
+                    output.push_str(line);
+                    output.push('\n');
+                }
+
+                expectation.output = Some(output);
+            }
+        }
+
+        if expectation.output.is_none() {
+            let golden = Path::new(path).with_extension("out");
+            expectation.output = fs::read_to_string(golden).ok();
+        }
+
+        expectation
+    }
+
+    /// Returns a description of the failure, if the expectation wasn't met
+    fn check(&self, error: Option<&str>, output: &str) -> Option<String> {
+        match (error, &self.error) {
+            (None, None) => {}
+            (Some(actual), Some(expected)) if actual == expected => {}
+            (Some(actual), Some(expected)) => {
+                return Some(format!(
+                    "Expected error `{}`, but got error `{}`",
+                    expected, actual,
+                ));
+            }
+            (Some(actual), None) => {
+                return Some(format!("Test failed with error `{}`", actual));
+            }
+            (None, Some(expected)) => {
+                return Some(format!(
+                    "Expected error `{}`, but test succeeded",
+                    expected,
+                ));
+            }
+        }
+
+        if let Some(expected) = &self.output {
+            // Whether output ends with a newline is hard to see in a comment,
+            // so it is ignored.
+            if expected.trim_end_matches('\n') != output.trim_end_matches('\n')
+            {
+                return Some(format!(
+                    "Unexpected output\nExpected:\n{}\nActual:\n{}",
+                    expected.trim_end_matches('\n'),
+                    output.trim_end_matches('\n'),
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+/// Captures output, so it can be checked after the test has run
+#[derive(Clone, Default)]
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
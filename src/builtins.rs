@@ -1,15 +1,59 @@
+use std::{cmp::Ordering, rc::Rc};
+
+use decorum::R32;
+
 use crate::{
     context::{self, Context},
-    functions::{Function, Functions, Scope},
+    format,
+    functions::{Effect, Function, Functions, Scope},
+    pipeline::Pipeline,
     prelude::*,
-    source::SpanMerge,
-    value::{self, t, v},
+    source::{Expression, ExpressionKind, Span, SpanMerge},
+    stack,
+    test_case::TestCase,
+    value::{
+        self,
+        cast::TypeError,
+        t,
+        types::{Type, Typed as _},
+        v,
+    },
 };
 
 pub type Result = std::result::Result<(), context::Error>;
 
+/// A value that a builtin function leaves on the stack
+///
+/// The checker uses these to follow the stack through calls to builtins,
+/// without running them.
+#[derive(Clone, Copy)]
+pub enum Output {
+    /// One of the inputs, counted from the top of the stack
+    Input(usize),
+
+    /// A value of this type
+    Type(&'static dyn Type),
+
+    /// A value of the same type as the first input
+    Same,
+
+    /// A value we know nothing about
+    Unknown,
+}
+
+/// Declares the builtin functions
+///
+/// Each function is declared with its name, signature, and the values it
+/// leaves on the stack. Those are either `?`, if they depend on the values the
+/// function is called with, or a list of outputs: the name of a type, the
+/// index of an input, `same` for the type of the first input, or `_` for a
+/// value we know nothing about.
 macro_rules! builtins {
-    ($($name:expr, $fn:ident, ($($arg:expr,)*);)*) => {
+    (
+        $(
+            $name:expr, $fn:ident, ($($arg:expr,)*) -> $outputs:tt;
+        )*
+    ) => {
         pub fn builtins<Host>(functions: &mut Functions<Function<Host>>) {
             let scope = functions.root_scope();
 
@@ -24,46 +68,186 @@ macro_rules! builtins {
                     .expect("Failed to define builtin")
                 )*;
         }
+
+        /// What the builtin function with this name and signature leaves on
+        /// the stack
+        ///
+        /// Returns `None`, if there's no such builtin, or if that depends on
+        /// the values it's called with, like for `eval`.
+        pub fn outputs(
+            name: &str,
+            signature: &[&'static dyn Type],
+        ) -> Option<&'static [Output]> {
+            $(
+                if name == $name && same_types(signature, &[$(&$arg,)*]) {
+                    return outputs!($outputs);
+                }
+            )*
+
+            None
+        }
     }
 }
 
+macro_rules! outputs {
+    (?) => {
+        None
+    };
+    (($($output:tt),*)) => {
+        Some(&[$(output!($output),)*])
+    };
+}
+
+macro_rules! output {
+    (_) => {
+        Output::Unknown
+    };
+    (same) => {
+        Output::Same
+    };
+    ($input:literal) => {
+        Output::Input($input)
+    };
+    ($ty:ident) => {
+        Output::Type(&t::$ty)
+    };
+}
+
+fn same_types(a: &[&'static dyn Type], b: &[&'static dyn Type]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| **a == **b)
+}
+
 builtins!(
-    "print",   print,    (t::Any,);
-    "define",  define,   (t::List, t::Symbol,);
-    "define",  define_s, (t::List, t::Symbol, t::Scope,);
-    "caller",  caller,   ();
-    "fail",    fail,     ();
-    "eval",    eval,     (t::List,);
-    "load",    load,     (t::String,);
-    "to_list", to_list,  (t::Symbol,);
-
-    "drop",  drop,  (t::Any,);
-    "clone", clone, (t::Any,);
-    "swap",  swap,  (t::Any, t::Any,);
-    "dig",   dig,   (t::Any, t::List,);
-
-    "if", r#if, (t::List, t::List,);
-
-    "list",    list,    (t::Number,);
-    "map",     map,     (t::List, t::List,);
-    "wrap",    wrap,    (t::Any,);
-    "unwrap",  unwrap,  (t::List,);
-    "prepend", prepend, (t::List, t::Any,);
-    "append",  append,  (t::List, t::Any,);
-
-    "+", add_n, (t::Number, t::Number,);
-    "-", sub_n, (t::Number, t::Number,);
-    "*", mul_n, (t::Number, t::Number,);
-    "/", div_n, (t::Number, t::Number,);
-    ">", gt_n,  (t::Number, t::Number,);
-
-    "+", add_f, (t::Float, t::Float,);
-    "-", sub_f, (t::Float, t::Float,);
-    "*", mul_f, (t::Float, t::Float,);
-    ">", gt_f,  (t::Float, t::Float,);
-
-    "=",   eq,  (t::Any, t::Any,);
-    "not", not, (t::Bool,);
+    "print",   print,    (t::Any,) -> ();
+    "define",  define,   (t::List, t::Symbol,) -> ();
+    "define",  define_s, (t::List, t::Symbol, t::Scope,) -> ();
+    "caller",  caller,   () -> (Scope);
+    "fail",    fail,     () -> ?;
+    "fail_with", fail_with, (t::Any,) -> ?;
+    "error",   error,    (t::Symbol, t::List,) -> (Error);
+    "error_kind", error_kind, (t::Error,) -> (Symbol);
+    "field",   field,    (t::Error, t::Symbol,) -> (_);
+    "try",     r#try,    (t::List, t::List,) -> ?;
+    "throw",   throw,    (t::Error,) -> ?;
+    "eval",    eval,     (t::List,) -> ?;
+    "load",    load,     (t::String,) -> (List);
+    "to_list", to_list,  (t::Symbol,) -> (List);
+    "test",    test,     (t::List, t::String,) -> ();
+
+    "drop",  drop,  (t::Any,) -> ();
+    "clone", clone, (t::Any,) -> (0, 0);
+    "swap",  swap,  (t::Any, t::Any,) -> (0, 1);
+    "dig",   dig,   (t::Any, t::List,) -> ?;
+    "over",  over,  (t::Any, t::Any,) -> (1, 0, 1);
+    "rot",   rot,   (t::Any, t::Any, t::Any,) -> (1, 0, 2);
+    "nip",   nip,   (t::Any, t::Any,) -> (0);
+    "tuck",  tuck,  (t::Any, t::Any,) -> (0, 1, 0);
+    "pick",  pick,  (t::Number,) -> ?;
+    "roll",  roll,  (t::Number,) -> ?;
+    "dup2",  dup2,  (t::Any, t::Any,) -> (1, 0, 1, 0);
+    "depth", depth, () -> (Number);
+    "clear", clear, () -> ?;
+    "stack", stack, () -> ?;
+
+    "if",      r#if,    (t::List, t::List,) -> ?;
+    "if_else", if_else, (t::List, t::List, t::List,) -> ?;
+    "while",   r#while, (t::List, t::List,) -> ?;
+    "loop",    r#loop,  (t::List,) -> ?;
+    "break",   r#break, () -> ?;
+    "cond",    cond,    (t::List,) -> ?;
+
+    "list",    list,    (t::Number,) -> ?;
+    "map",     map,     (t::List, t::List,) -> ?;
+    "wrap",    wrap,    (t::Any,) -> (List);
+    "unwrap",  unwrap,  (t::List,) -> ?;
+    "prepend", prepend, (t::List, t::Any,) -> (List);
+    "append",  append,  (t::List, t::Any,) -> (List);
+
+    "len",       len,       (t::List,) -> (Number);
+    "get",       get,       (t::List, t::Number,) -> (_);
+    "set",       set,       (t::List, t::Number, t::Any,) -> (List);
+    "slice",     slice,     (t::List, t::Number, t::Number,) -> (List);
+    "concat",    concat,    (t::List, t::List,) -> (same);
+    "reverse",   reverse,   (t::List,) -> (List);
+    "filter",    filter,    (t::List, t::List,) -> ?;
+    "reduce",    reduce,    (t::Any, t::List, t::List,) -> ?;
+    "sort",      sort,      (t::List,) -> (List);
+    "sort_by",   sort_by,   (t::List, t::List,) -> ?;
+    "zip",       zip,       (t::List, t::List,) -> (List);
+    "enumerate", enumerate, (t::List,) -> (List);
+    "range",     range,     (t::Number, t::Number,) -> (List);
+    "contains",  contains,  (t::List, t::Any,) -> (Bool);
+    "find",      find,      (t::List, t::List,) -> ?;
+
+    "concat",      concat_s,    (t::String, t::String,) -> (same);
+    "len",         len_s,       (t::String,) -> (Number);
+    "byte_len",    byte_len,    (t::String,) -> (Number);
+    "split",       split,       (t::String, t::String,) -> (List);
+    "join",        join,        (t::List, t::String,) -> (String);
+    "contains",    contains_s,  (t::String, t::String,) -> (Bool);
+    "starts_with", starts_with, (t::String, t::String,) -> (Bool);
+    "replace",     replace,     (t::String, t::String, t::String,) -> (String);
+    "trim",        trim,        (t::String,) -> (String);
+    "upper",       upper,       (t::String,) -> (String);
+    "lower",       lower,       (t::String,) -> (String);
+    "chars",       chars,       (t::String,) -> (List);
+    "substring",   substring,   (t::String, t::Number, t::Number,) -> (String);
+    "format",      format,      (t::String,) -> ?;
+
+    "repr",  repr,  (t::Any,) -> (String);
+    "parse", parse, (t::String,) -> (List);
+
+    "type_of",      type_of,      (t::Any,) -> (Symbol);
+    "defined?",     is_defined,   (t::Symbol,) -> (Bool);
+    "signatures",   signatures,   (t::Symbol,) -> (List);
+    "functions_in", functions_in, (t::Scope,) -> (List);
+    "on_stack",     on_stack,     (t::List,) -> ();
+    "effect",       effect,       (t::List,) -> ();
+
+    "+",        add_n,    (t::Number, t::Number,) -> (same);
+    "-",        sub_n,    (t::Number, t::Number,) -> (same);
+    "*",        mul_n,    (t::Number, t::Number,) -> (same);
+    "/",        div_n,    (t::Number, t::Number,) -> (same);
+    "%",        rem_n,    (t::Number, t::Number,) -> (same);
+    ">",        gt_n,     (t::Number, t::Number,) -> (Bool);
+    "<",        lt_n,     (t::Number, t::Number,) -> (Bool);
+    "<=",       le_n,     (t::Number, t::Number,) -> (Bool);
+    ">=",       ge_n,     (t::Number, t::Number,) -> (Bool);
+    "min",      min_n,    (t::Number, t::Number,) -> (same);
+    "max",      max_n,    (t::Number, t::Number,) -> (same);
+    "pow",      pow_n,    (t::Number, t::Number,) -> (same);
+    "to_float", to_float, (t::Number,) -> (Float);
+
+    "+",         add_f,     (t::Float, t::Float,) -> (same);
+    "-",         sub_f,     (t::Float, t::Float,) -> (same);
+    "*",         mul_f,     (t::Float, t::Float,) -> (same);
+    "/",         div_f,     (t::Float, t::Float,) -> (same);
+    "%",         rem_f,     (t::Float, t::Float,) -> (same);
+    ">",         gt_f,      (t::Float, t::Float,) -> (Bool);
+    "<",         lt_f,      (t::Float, t::Float,) -> (Bool);
+    "<=",        le_f,      (t::Float, t::Float,) -> (Bool);
+    ">=",        ge_f,      (t::Float, t::Float,) -> (Bool);
+    "min",       min_f,     (t::Float, t::Float,) -> (same);
+    "max",       max_f,     (t::Float, t::Float,) -> (same);
+    "pow",       pow_f,     (t::Float, t::Float,) -> (same);
+    "abs",       abs_f,     (t::Float,) -> (same);
+    "sqrt",      sqrt,      (t::Float,) -> (same);
+    "floor",     floor,     (t::Float,) -> (same);
+    "ceil",      ceil,      (t::Float,) -> (same);
+    "round",     round,     (t::Float,) -> (same);
+    "to_number", to_number, (t::Float,) -> (Number);
+
+    "=",   eq,  (t::Any, t::Any,) -> (Bool);
+    "assert_eq", assert_eq, (t::Any, t::Any,) -> ();
+    "not", not, (t::Bool,) -> (Bool);
+
+    "and",      and,      (t::Bool, t::Bool,) -> (Bool);
+    "or",       or,       (t::Bool, t::Bool,) -> (Bool);
+    "xor",      xor,      (t::Bool, t::Bool,) -> (Bool);
+    "and_then", and_then, (t::List, t::List,) -> ?;
+    "or_else",  or_else,  (t::List, t::List,) -> ?;
+    "all",      all,      (t::List,) -> ?;
+    "any",      any,      (t::List,) -> ?;
 );
 
 fn print<Host>(
@@ -87,12 +271,8 @@ fn define<Host>(
         .pop::<(_, _)>()?
         .cast((t::List, t::Symbol))?;
 
-    context.functions().define(
-        scope,
-        name.inner,
-        &[],
-        Function::UserDefined { body },
-    )?;
+    let (args, function) = user_defined(body)?;
+    context.define(scope, name.inner, &args, function)?;
 
     Ok(())
 }
@@ -108,12 +288,57 @@ fn define_s<Host>(
         t::Scope,
     ))?;
 
-    context.functions().define(
-        scope.inner,
-        name.inner,
-        &[],
-        Function::UserDefined { body },
-    )?;
+    let (args, function) = user_defined(body)?;
+    context.define(scope.inner, name.inner, &args, function)?;
+
+    Ok(())
+}
+
+/// Create a user-defined function from its body
+///
+/// If the body starts with a stack effect, like `( number -- number )`, it is
+/// removed from the body and checked whenever the function is called. Its
+/// inputs are returned as the types that the function is defined for.
+fn user_defined<Host>(
+    mut body: v::List,
+) -> std::result::Result<(Vec<&'static dyn Type>, Function<Host>), context::Error>
+{
+    let items = &body.inner.items;
+    let declaration = match (items.first(), items.get(1)) {
+        (Some(list), Some(word))
+            if word.kind == value::Kind::Word(String::from("effect")) =>
+        {
+            Some(list.clone().cast(t::List)?)
+        }
+        _ => None,
+    };
+
+    let effect = match declaration {
+        Some(declaration) => {
+            body.inner.items.drain(..2);
+            Some(Rc::new(Effect::parse(&declaration)?))
+        }
+        None => None,
+    };
+    let args = effect
+        .as_ref()
+        .map(|effect| effect.inputs.clone())
+        .unwrap_or_default();
+
+    Ok((args, Function::UserDefined { body, effect }))
+}
+
+/// Validate a stack effect that isn't at the start of a function definition
+///
+/// Stack effects only have meaning as part of a function definition, where
+/// `define` removes them. Anywhere else, they do nothing.
+fn effect<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let declaration = context.stack().pop::<v::Any>()?.cast(t::List)?;
+    Effect::parse(&declaration)?;
 
     Ok(())
 }
@@ -136,7 +361,126 @@ fn caller<Host>(
 }
 
 fn fail<Host>(_: &mut Host, _: &mut dyn Context<Host>, _: Scope) -> Result {
-    Err(context::Error::Failure)
+    Err(context::Error::Failure(None))
+}
+
+fn fail_with<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let value = context.stack().pop::<v::Any>()?;
+    Err(context::Error::Failure(Some(value)))
+}
+
+fn r#try<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (body, handler) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    // The body might consume values that were on the stack before it, so
+    // only knowing the depth of the stack isn't enough to restore it.
+    let stack = context.stack().clone();
+    let frames = context.call_stack().frames.len();
+
+    let error = match context.evaluate_list(host, body) {
+        Ok(()) => return Ok(()),
+        Err(context::Error::Break) => return Err(context::Error::Break),
+        Err(error) => error,
+    };
+
+    // The error might have been returned from deep within nested function
+    // calls. Clean up whatever they left behind.
+    *context.stack() = stack;
+    context.call_stack().frames.truncate(frames);
+
+    let span = context.call_stack().operator().span.clone();
+    context.stack().push(error.into_value(span));
+
+    context.evaluate_list(host, handler)
+}
+
+fn throw<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let error = context.stack().pop::<v::Any>()?.cast(t::Error)?;
+    Err(context::Error::Failure(Some(error.into_any())))
+}
+
+fn error<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (kind, fields) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Symbol, t::List))?;
+
+    let span = kind.span.clone().merge(fields.span.clone());
+
+    let mut items = fields.into_iter();
+    let mut fields = Vec::new();
+
+    while let Some(name) = items.next() {
+        let name = name.cast(t::Symbol)?;
+        let value = match items.next() {
+            Some(value) => value,
+            None => return Err(context::Error::FieldWithoutValue(name)),
+        };
+
+        fields.push((name.inner, value));
+    }
+
+    let error = Box::new(value::ErrorInner {
+        kind: kind.inner,
+        fields,
+    });
+    context.stack().push(v::Error::new(error, span));
+
+    Ok(())
+}
+
+fn error_kind<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let error = context.stack().pop::<v::Any>()?.cast(t::Error)?;
+
+    let (error, span) = error.open();
+    context.stack().push(v::Symbol::new(error.kind, span));
+
+    Ok(())
+}
+
+fn field<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (error, name) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Error, t::Symbol))?;
+
+    let value = match error.inner.field(&name.inner) {
+        Some(value) => value.clone(),
+        None => {
+            return Err(context::Error::FieldNotFound {
+                kind: error.inner.kind,
+                name: Box::new(name),
+            })
+        }
+    };
+    context.stack().push(value);
+
+    Ok(())
 }
 
 fn eval<Host>(
@@ -182,6 +526,26 @@ fn load<Host>(
     Ok(())
 }
 
+fn test<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (body, name) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::List, t::String))?;
+
+    let span = context.call_stack().operator().span.clone();
+    context.test_cases().push(TestCase {
+        name: name.inner,
+        body,
+        span,
+    });
+
+    Ok(())
+}
+
 fn to_list<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
@@ -260,236 +624,1583 @@ fn dig<Host>(
     Ok(())
 }
 
-fn r#if<Host>(
-    host: &mut Host,
+fn over<Host>(
+    _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let (function, condition) =
-        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
-
-    context.evaluate_list(host, condition)?;
-
-    let evaluated_condition = context.stack().pop::<v::Any>()?.cast(t::Bool)?;
-
-    if evaluated_condition.inner {
-        context.evaluate_list(host, function)?;
-    }
+    let (a, b) = context.stack().pop::<(_, _)>()?;
+    let copy = copy(context, &a);
+    context.stack().push((a, b)).push(copy);
 
     Ok(())
 }
 
-fn list<Host>(
+fn rot<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
-    scope: Scope,
-) -> Result {
-    let len = context.stack().pop::<v::Any>()?.cast(t::Number)?;
-
-    let mut items = Vec::new();
-    let mut span = len.span;
-
-    for _ in 0..len.inner {
-        let item = context.stack().pop::<v::Any>()?;
-
-        span = span.merge(item.span.clone());
-        items.insert(0, item);
-    }
-
-    let list = v::List::new(
-        value::ListInner::from_values(
-            items,
-            context.functions().new_scope(scope, "list"),
-        ),
-        span,
-    );
-
-    context.stack().push(list);
-
-    Ok(())
-}
-
-fn map<Host>(
-    host: &mut Host,
-    context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let (list, function) =
-        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
-
-    context.stack().create_substack();
-
-    for item in list.inner.items {
-        context.stack().push(item);
-        context.evaluate_list(host, function.clone())?;
-    }
-
-    let result = context.stack().destroy_substack();
-
-    let data = v::List::new(
-        value::ListInner::from_values(
-            result,
-            context.functions().new_scope(list.inner.scope, "list"),
-        ),
-        context
-            .call_stack()
-            .operator()
-            .span
-            .clone()
-            .merge(list.span.merge(function.span)),
-    );
-    context.stack().push(data);
+    let (a, b, c) = context.stack().pop::<(_, _, _)>()?;
+    context.stack().push((b, c)).push(a);
 
     Ok(())
 }
 
-fn wrap<Host>(
+fn nip<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
-    scope: Scope,
+    _: Scope,
 ) -> Result {
-    let arg = context.stack().pop::<v::Any>()?;
-
-    let span = context
-        .call_stack()
-        .operator()
-        .span
-        .clone()
-        .merge(arg.span.clone());
-    let list = v::List::new(
-        value::ListInner::from_values(
-            vec![arg],
-            context.functions().new_scope(scope, "list"),
-        ),
-        span,
-    );
-
-    context.stack().push(list);
+    let (_, b) = context.stack().pop::<(v::Any, v::Any)>()?;
+    context.stack().push(b);
 
     Ok(())
 }
 
-fn unwrap<Host>(
+fn tuck<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let list = context.stack().pop::<v::Any>()?.cast(t::List)?;
-
-    for value in list.inner.items {
-        context.stack().push(value);
-    }
+    let (a, b) = context.stack().pop::<(_, _)>()?;
+    let copy = copy(context, &b);
+    context.stack().push((copy, a)).push(b);
 
     Ok(())
 }
 
-fn prepend<Host>(
+fn pick<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let (mut list, arg) =
-        context.stack().pop::<(_, _)>()?.cast((t::List, t::Any))?;
-
-    list.span = context
-        .call_stack()
-        .operator()
-        .span
-        .clone()
-        .merge(list.span.merge(arg.span.clone()));
-    list.inner.items.insert(0, arg);
+    let n = context.stack().pop::<v::Any>()?.cast(t::Number)?;
 
-    context.stack().push(list);
+    let value = match context.stack().peek().nth(n.inner as usize) {
+        Some(value) => value.clone(),
+        None => return Err(stack::Error::StackEmpty.into()),
+    };
+    let copy = copy(context, &value);
+    context.stack().push(copy);
 
     Ok(())
 }
 
-fn append<Host>(
+fn roll<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let (mut list, arg) =
-        context.stack().pop::<(_, _)>()?.cast((t::List, t::Any))?;
-
-    list.span = context
-        .call_stack()
-        .operator()
-        .span
-        .clone()
-        .merge(list.span.merge(arg.span.clone()));
-    list.inner.items.push(arg);
+    let n = context.stack().pop::<v::Any>()?.cast(t::Number)?;
 
-    context.stack().push(list);
+    let value = context
+        .stack()
+        .remove(n.inner as usize)
+        .ok_or(stack::Error::StackEmpty)?;
+    context.stack().push(value);
 
     Ok(())
 }
 
-fn add_n<Host>(
+fn dup2<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let sum = context
-        .stack()
-        .pop::<(_, _)>()?
-        .cast((t::Number, t::Number))?
-        .compute::<v::Number, _, _>(|(a, b)| a + b);
-
-    context.stack().push(sum);
+    let (a, b) = context.stack().pop::<(_, _)>()?;
+    let copies = (copy(context, &a), copy(context, &b));
+    context.stack().push((a, b)).push(copies);
 
     Ok(())
 }
 
-fn sub_n<Host>(
+fn depth<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let difference = context
-        .stack()
-        .pop::<(_, _)>()?
-        .cast((t::Number, t::Number))?
-        .compute::<v::Number, _, _>(|(a, b)| a - b);
-
-    context.stack().push(difference);
+    let depth = context.stack().len() as u32;
+    let span = context.call_stack().operator().span.clone();
+    context.stack().push(v::Number::new(depth, span));
 
     Ok(())
 }
 
-fn mul_n<Host>(
+fn clear<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let product = context
-        .stack()
-        .pop::<(_, _)>()?
-        .cast((t::Number, t::Number))?
-        .compute::<v::Number, _, _>(|(a, b)| a * b);
-
-    context.stack().push(product);
-
+    context.stack().clear();
     Ok(())
 }
 
-fn div_n<Host>(
+fn stack<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
-    _: Scope,
+    scope: Scope,
+) -> Result {
+    let mut items: Vec<_> = context.stack().peek().cloned().collect();
+    items.reverse();
+
+    let list = v::List::new(
+        value::ListInner::from_values(
+            items,
+            context.functions().new_scope(scope, "list"),
+        ),
+        context.call_stack().operator().span.clone(),
+    );
+    context.stack().push(list);
+
+    Ok(())
+}
+
+/// Copy a value, like `clone` does
+fn copy<Host>(
+    context: &mut dyn Context<Host>,
+    value: &value::Any,
+) -> value::Any {
+    let mut copy = value.clone();
+    copy.span = context
+        .call_stack()
+        .operator()
+        .span
+        .clone()
+        .merge(copy.span);
+    copy
+}
+
+fn r#if<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (function, condition) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    if evaluate_condition(host, context, condition)? {
+        context.evaluate_list(host, function)?;
+    }
+
+    Ok(())
+}
+
+fn if_else<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (then, otherwise, condition) = context
+        .stack()
+        .pop::<(_, _, _)>()?
+        .cast((t::List, t::List, t::List))?;
+
+    if evaluate_condition(host, context, condition)? {
+        context.evaluate_list(host, then)
+    } else {
+        context.evaluate_list(host, otherwise)
+    }
+}
+
+fn r#while<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (body, condition) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    while evaluate_condition(host, context, condition.clone())? {
+        if !evaluate_iteration(host, context, body.clone())? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn r#loop<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let body = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    while evaluate_iteration(host, context, body.clone())? {}
+
+    Ok(())
+}
+
+/// Evaluate the body of a loop once
+///
+/// Returns `false`, if `break` was called from within the body.
+fn evaluate_iteration<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    body: v::List,
+) -> std::result::Result<bool, context::Error> {
+    let depth = context.stack().depth();
+    let frames = context.call_stack().frames.len();
+
+    match context.evaluate_list(host, body) {
+        Ok(()) => Ok(true),
+        Err(context::Error::Break) => {
+            // `break` might have been called from within nested function
+            // calls or evaluated lists. Clean up whatever they left behind.
+            context.stack().restore(depth);
+            context.call_stack().frames.truncate(frames);
+            Ok(false)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+fn r#break<Host>(_: &mut Host, _: &mut dyn Context<Host>, _: Scope) -> Result {
+    Err(context::Error::Break)
+}
+
+fn cond<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let cases = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    for case in cases {
+        let (condition, body) = match &case.kind {
+            value::Kind::List(inner) if inner.items.len() == 2 => {
+                let condition = inner.items[0].clone().cast(t::List)?;
+                let body = inner.items[1].clone().cast(t::List)?;
+                (condition, body)
+            }
+            _ => return Err(context::Error::InvalidCase(case)),
+        };
+
+        if evaluate_condition(host, context, condition)? {
+            return context.evaluate_list(host, body);
+        }
+    }
+
+    Ok(())
+}
+
+/// Evaluate a condition, returning the bool it leaves on the stack
+fn evaluate_condition<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    condition: v::List,
+) -> std::result::Result<bool, context::Error> {
+    context.evaluate_list(host, condition)?;
+    let evaluated_condition = context.stack().pop::<v::Any>()?.cast(t::Bool)?;
+    Ok(evaluated_condition.inner)
+}
+
+fn list<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let len = context.stack().pop::<v::Any>()?.cast(t::Number)?;
+
+    let mut items = Vec::new();
+    let mut span = len.span;
+
+    for _ in 0..len.inner {
+        let item = context.stack().pop::<v::Any>()?;
+
+        span = span.merge(item.span.clone());
+        items.insert(0, item);
+    }
+
+    let list = v::List::new(
+        value::ListInner::from_values(
+            items,
+            context.functions().new_scope(scope, "list"),
+        ),
+        span,
+    );
+
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn map<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (list, function) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    context.stack().create_substack();
+
+    for item in list.inner.items {
+        context.stack().push(item);
+        context.evaluate_list(host, function.clone())?;
+    }
+
+    let result = context.stack().destroy_substack();
+
+    let data = v::List::new(
+        value::ListInner::from_values(
+            result,
+            context.functions().new_scope(list.inner.scope, "list"),
+        ),
+        context
+            .call_stack()
+            .operator()
+            .span
+            .clone()
+            .merge(list.span.merge(function.span)),
+    );
+    context.stack().push(data);
+
+    Ok(())
+}
+
+fn wrap<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let arg = context.stack().pop::<v::Any>()?;
+
+    let span = context
+        .call_stack()
+        .operator()
+        .span
+        .clone()
+        .merge(arg.span.clone());
+    let list = v::List::new(
+        value::ListInner::from_values(
+            vec![arg],
+            context.functions().new_scope(scope, "list"),
+        ),
+        span,
+    );
+
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn unwrap<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let list = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    for value in list.inner.items {
+        context.stack().push(value);
+    }
+
+    Ok(())
+}
+
+fn prepend<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (mut list, arg) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::Any))?;
+
+    list.span = context
+        .call_stack()
+        .operator()
+        .span
+        .clone()
+        .merge(list.span.merge(arg.span.clone()));
+    list.inner.items.insert(0, arg);
+
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn append<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (mut list, arg) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::Any))?;
+
+    list.span = context
+        .call_stack()
+        .operator()
+        .span
+        .clone()
+        .merge(list.span.merge(arg.span.clone()));
+    list.inner.items.push(arg);
+
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn len<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let list = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    let len = list.inner.items.len() as u32;
+    let span = operator_span(context).merge(list.span);
+    context.stack().push(v::Number::new(len, span));
+
+    Ok(())
+}
+
+fn get<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (list, index) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::List, t::Number))?;
+
+    let i = check_index(&index, list.inner.items.len())?;
+    let mut item = list.inner.items[i].clone();
+    item.span = operator_span(context).merge(item.span);
+    context.stack().push(item);
+
+    Ok(())
+}
+
+fn set<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (mut list, index, item) = context
+        .stack()
+        .pop::<(_, _, _)>()?
+        .cast((t::List, t::Number, t::Any))?;
+
+    let i = check_index(&index, list.inner.items.len())?;
+    list.inner.items[i] = item;
+    list.span = operator_span(context).merge(list.span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn slice<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (mut list, start, end) = context.stack().pop::<(_, _, _)>()?.cast((
+        t::List,
+        t::Number,
+        t::Number,
+    ))?;
+
+    // The end of the range is exclusive, so it can be equal to the length.
+    let len = list.inner.items.len();
+    let end = check_index(&end, len + 1)?;
+    let start = check_index(&start, end + 1)?;
+
+    list.inner.items = list.inner.items.drain(start..end).collect();
+    list.span = operator_span(context).merge(list.span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn concat<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (mut a, b) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    a.inner.items.extend(b.inner.items);
+    a.span = operator_span(context).merge(a.span.merge(b.span));
+    context.stack().push(a);
+
+    Ok(())
+}
+
+fn reverse<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let mut list = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    list.inner.items.reverse();
+    list.span = operator_span(context).merge(list.span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn filter<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (mut list, predicate) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    let mut items = Vec::new();
+    for item in list.inner.items {
+        context.stack().push(item.clone());
+        if evaluate_condition(host, context, predicate.clone())? {
+            items.push(item);
+        }
+    }
+
+    list.inner.items = items;
+    list.span = operator_span(context).merge(list.span.merge(predicate.span));
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn reduce<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (initial, list, function) = context
+        .stack()
+        .pop::<(_, _, _)>()?
+        .cast((t::Any, t::List, t::List))?;
+
+    context.stack().push(initial);
+    for item in list {
+        context.stack().push(item);
+        context.evaluate_list(host, function.clone())?;
+    }
+
+    Ok(())
+}
+
+fn sort<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let mut list = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    let mut error = None;
+    list.inner.items.sort_by(|a, b| {
+        if error.is_some() {
+            return Ordering::Equal;
+        }
+
+        match (&a.kind, &b.kind) {
+            (value::Kind::Bool(a), value::Kind::Bool(b)) => a.cmp(b),
+            (value::Kind::Float(a), value::Kind::Float(b)) => a.cmp(b),
+            (value::Kind::Number(a), value::Kind::Number(b)) => a.cmp(b),
+            (value::Kind::String(a), value::Kind::String(b)) => a.cmp(b),
+            (value::Kind::Symbol(a), value::Kind::Symbol(b)) => a.cmp(b),
+            _ => {
+                // Only values of the same type can be compared, and not all
+                // types can be compared.
+                let expected = match a.kind {
+                    value::Kind::Bool(_)
+                    | value::Kind::Float(_)
+                    | value::Kind::Number(_)
+                    | value::Kind::String(_)
+                    | value::Kind::Symbol(_) => a.get_type().name(),
+                    _ => "bool, float, number, string, or symbol",
+                };
+                let actual = if a.get_type().name() == expected {
+                    b.clone()
+                } else {
+                    a.clone()
+                };
+
+                error = Some(TypeError {
+                    expected,
+                    actual: Box::new(actual),
+                });
+                Ordering::Equal
+            }
+        }
+    });
+    if let Some(error) = error {
+        return Err(error.into());
+    }
+
+    list.span = operator_span(context).merge(list.span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn sort_by<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (mut list, less_than) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    let mut is_less = |a: &value::Any, b: &value::Any| {
+        context.stack().push((a.clone(), b.clone()));
+        evaluate_condition(host, context, less_than.clone())
+    };
+
+    // The comparator can fail, but `sort_by` has no way to abort. Remember the
+    // first error and treat all further comparisons as equal.
+    let mut error = None;
+    list.inner.items.sort_by(|a, b| {
+        if error.is_some() {
+            return Ordering::Equal;
+        }
+
+        let ordering = match is_less(a, b) {
+            Ok(true) => Ok(Ordering::Less),
+            Ok(false) => is_less(b, a).map(|is_greater| {
+                if is_greater {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            }),
+            Err(err) => Err(err),
+        };
+
+        ordering.unwrap_or_else(|err| {
+            error = Some(err);
+            Ordering::Equal
+        })
+    });
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    list.span = operator_span(context).merge(list.span.merge(less_than.span));
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn zip<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let (a, b) = context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    let span = operator_span(context).merge(a.span.merge(b.span));
+
+    let items = a
+        .inner
+        .items
+        .into_iter()
+        .zip(b.inner.items)
+        .map(|(a, b)| {
+            let pair = new_list(context, scope, vec![a, b], span.clone());
+            pair.into_any()
+        })
+        .collect();
+
+    let list = new_list(context, scope, items, span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn enumerate<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let list = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    let span = operator_span(context).merge(list.span);
+
+    let items = list
+        .inner
+        .items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let index = v::Number::new(i as u32, span.clone()).into_any();
+            let pair =
+                new_list(context, scope, vec![index, item], span.clone());
+            pair.into_any()
+        })
+        .collect();
+
+    let list = new_list(context, scope, items, span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn range<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let (start, end) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?;
+
+    let span = operator_span(context).merge(start.span.merge(end.span));
+
+    let items = (start.inner..end.inner)
+        .map(|i| v::Number::new(i, span.clone()).into_any())
+        .collect();
+
+    let list = new_list(context, scope, items, span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn contains<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (list, item) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::Any))?;
+
+    let contains = list.inner.items.iter().any(|i| i.kind == item.kind);
+    let span = operator_span(context).merge(list.span.merge(item.span));
+    context.stack().push(v::Bool::new(contains, span));
+
+    Ok(())
+}
+
+/// Find the first item that matches a predicate
+///
+/// Pushes a list that contains the item, or an empty list, if no item matches.
+fn find<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let (list, predicate) =
+        context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    let mut found = Vec::new();
+    for item in list.inner.items {
+        context.stack().push(item.clone());
+        if evaluate_condition(host, context, predicate.clone())? {
+            found.push(item);
+            break;
+        }
+    }
+
+    let span = operator_span(context).merge(list.span.merge(predicate.span));
+    let list = new_list(context, scope, found, span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn concat_s<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let string = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::String, t::String))?
+        .compute::<v::String, _, _>(|(a, b)| a + &b);
+
+    context.stack().push(string);
+
+    Ok(())
+}
+
+/// The length of a string in characters
+fn len_s<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let len = context
+        .stack()
+        .pop::<v::Any>()?
+        .cast(t::String)?
+        .compute::<v::Number, _, _>(|s| s.chars().count() as u32);
+
+    context.stack().push(len);
+
+    Ok(())
+}
+
+/// The length of a string in bytes, when encoded as UTF-8
+fn byte_len<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let len = context
+        .stack()
+        .pop::<v::Any>()?
+        .cast(t::String)?
+        .compute::<v::Number, _, _>(|s| s.len() as u32);
+
+    context.stack().push(len);
+
+    Ok(())
+}
+
+fn split<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let (string, separator) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::String, t::String))?;
+
+    let span = operator_span(context).merge(string.span.merge(separator.span));
+    let items = string
+        .inner
+        .split(separator.inner.as_str())
+        .map(|part| v::String::new(part.into(), span.clone()).into_any())
+        .collect();
+
+    let list = new_list(context, scope, items, span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn join<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (list, separator) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::List, t::String))?;
+
+    let mut parts = Vec::new();
+    for item in list.inner.items {
+        parts.push(item.cast(t::String)?.inner);
+    }
+
+    let span = operator_span(context).merge(list.span.merge(separator.span));
+    let string = v::String::new(parts.join(&separator.inner), span);
+    context.stack().push(string);
+
+    Ok(())
+}
+
+fn contains_s<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let contains = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::String, t::String))?
+        .compute::<v::Bool, _, _>(|(a, b)| a.contains(b.as_str()));
+
+    context.stack().push(contains);
+
+    Ok(())
+}
+
+fn starts_with<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let starts_with = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::String, t::String))?
+        .compute::<v::Bool, _, _>(|(a, b)| a.starts_with(b.as_str()));
+
+    context.stack().push(starts_with);
+
+    Ok(())
+}
+
+fn replace<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let string = context
+        .stack()
+        .pop::<(_, _, _)>()?
+        .cast((t::String, t::String, t::String))?
+        .compute::<v::String, _, _>(|(s, from, to)| s.replace(&from, &to));
+
+    context.stack().push(string);
+
+    Ok(())
+}
+
+fn trim<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let string = context
+        .stack()
+        .pop::<v::Any>()?
+        .cast(t::String)?
+        .compute::<v::String, _, _>(|s| s.trim().into());
+
+    context.stack().push(string);
+
+    Ok(())
+}
+
+fn upper<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let string = context
+        .stack()
+        .pop::<v::Any>()?
+        .cast(t::String)?
+        .compute::<v::String, _, _>(|s| s.to_uppercase());
+
+    context.stack().push(string);
+
+    Ok(())
+}
+
+fn lower<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let string = context
+        .stack()
+        .pop::<v::Any>()?
+        .cast(t::String)?
+        .compute::<v::String, _, _>(|s| s.to_lowercase());
+
+    context.stack().push(string);
+
+    Ok(())
+}
+
+/// Split a string into a list of single-character strings
+fn chars<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let string = context.stack().pop::<v::Any>()?.cast(t::String)?;
+
+    let items = string
+        .inner
+        .chars()
+        .map(|c| v::String::new(c.into(), string.span.clone()).into_any())
+        .collect();
+
+    let span = operator_span(context).merge(string.span);
+    let list = new_list(context, scope, items, span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+/// The characters of a string from `start` up to, but excluding, `end`
+fn substring<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (string, start, end) = context.stack().pop::<(_, _, _)>()?.cast((
+        t::String,
+        t::Number,
+        t::Number,
+    ))?;
+
+    let len = string.inner.chars().count();
+    let end_i = check_index(&end, len + 1)?;
+    let start_i = check_index(&start, end_i + 1)?;
+
+    let substring =
+        (string, start, end).compute::<v::String, _, _>(|(s, _, _)| {
+            s.chars().skip(start_i).take(end_i - start_i).collect()
+        });
+    context.stack().push(substring);
+
+    Ok(())
+}
+
+/// Replace the placeholders in a template with values from the stack
+///
+/// Takes one value per placeholder. The deepest value replaces the first
+/// placeholder.
+fn format<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let template = context.stack().pop::<v::Any>()?.cast(t::String)?;
+
+    let error = |error: format::Error| {
+        let span = template.span.as_ref().and_then(|span| {
+            format::span_within(span, &template.inner, error.range.clone())
+        });
+        context::Error::Format {
+            span: span.or_else(|| template.span.clone()),
+            error,
+        }
+    };
+
+    let parsed = format::Template::parse(&template.inner).map_err(error)?;
+    parsed.check_values(context.stack().len()).map_err(error)?;
+
+    let mut values = Vec::new();
+    for _ in parsed.placeholders() {
+        values.push(context.stack().pop::<v::Any>()?);
+    }
+    values.reverse();
+
+    let span = values.iter().fold(template.span.clone(), |span, value| {
+        span.merge(value.span.clone())
+    });
+    let string = v::String::new(parsed.render(&values), span);
+    context.stack().push(string);
+
+    Ok(())
+}
+
+/// Convert a value into Kari source code
+fn repr<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let value = context.stack().pop::<v::Any>()?;
+
+    let repr = match value.kind.repr() {
+        Some(repr) => repr,
+        None => {
+            return Err(context::Error::Conversion {
+                value: Box::new(value),
+                target: "source code",
+            });
+        }
+    };
+
+    context.stack().push(v::String::new(repr, value.span));
+
+    Ok(())
+}
+
+/// Parse a string of Kari source code into a list, without evaluating it
+fn parse<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let source = context.stack().pop::<v::Any>()?.cast(t::String)?;
+
+    // The tokenizer only finishes a word, once it sees the whitespace after
+    // it.
+    let stream = format!("{}\n", source.inner);
+
+    // The parsed code has no stream of its own that errors could point to.
+    // The string it came from is the next best thing.
+    let mut expressions =
+        Pipeline::new(String::from("<parse>"), stream.as_bytes())
+            .parser
+            .expressions(&mut String::new())
+            .map_err(|mut error| {
+                error.replace_spans(source.span.clone());
+                context::Error::Parser(error)
+            })?;
+    for expression in &mut expressions {
+        replace_spans(expression, &source.span);
+    }
+
+    let list = v::List::new(
+        value::ListInner::from_expressions(
+            expressions,
+            context.functions().new_scope(scope, "list"),
+        ),
+        source.span,
+    );
+    context.stack().push(list);
+
+    Ok(())
+}
+
+fn replace_spans(expression: &mut Expression, span: &Option<Span>) {
+    expression.span = span.clone();
+
+    if let ExpressionKind::List(expressions) = &mut expression.kind {
+        for expression in expressions {
+            replace_spans(expression, span);
+        }
+    }
+}
+
+fn type_of<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let value = context.stack().pop::<v::Any>()?;
+
+    let name = value.get_type().name();
+    context
+        .stack()
+        .push(v::Symbol::new(name.into(), value.span));
+
+    Ok(())
+}
+
+/// Whether a function of that name is visible from the current scope
+fn is_defined<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let name = context.stack().pop::<v::Any>()?.cast(t::Symbol)?;
+
+    let is_defined = context.functions().is_defined(scope, &name.inner);
+    context.stack().push(v::Bool::new(is_defined, name.span));
+
+    Ok(())
+}
+
+/// The argument types of all functions of that name, as lists of symbols
+fn signatures<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let name = context.stack().pop::<v::Any>()?.cast(t::Symbol)?;
+
+    let signatures = context.functions().signatures(scope, &name.inner);
+    let items = signatures
+        .into_iter()
+        .map(|signature| {
+            let types = signature
+                .into_iter()
+                .map(|t| {
+                    v::Symbol::new(t.name().into(), name.span.clone())
+                        .into_any()
+                })
+                .collect();
+            new_list(context, scope, types, name.span.clone()).into_any()
+        })
+        .collect();
+
+    let list = new_list(context, scope, items, name.span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+/// The names of the functions that are defined in a scope, as symbols
+///
+/// Doesn't include the functions that are defined in parent scopes.
+fn functions_in<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+) -> Result {
+    let target = context.stack().pop::<v::Any>()?.cast(t::Scope)?;
+
+    let items = context
+        .functions()
+        .names(target.inner)
+        .into_iter()
+        .map(|name| v::Symbol::new(name.into(), target.span.clone()).into_any())
+        .collect();
+
+    let list = new_list(context, scope, items, target.span);
+    context.stack().push(list);
+
+    Ok(())
+}
+
+/// Check that the top of the stack matches a stack annotation
+///
+/// The annotation lists type names, like `[ string number ]`, the last of which
+/// describes the top of the stack. `any` matches values of all types. The
+/// checked values are left on the stack.
+fn on_stack<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let annotation = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    let expected = annotation
+        .inner
+        .items
+        .iter()
+        .map(|item| item.clone().cast(t::Word))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let mut actual: Vec<_> = context
+        .stack()
+        .peek()
+        .take(expected.len())
+        .cloned()
+        .collect();
+    actual.reverse();
+
+    let matches = actual.len() == expected.len()
+        && expected.iter().zip(&actual).all(|(expected, actual)| {
+            expected.inner == t::Any.name()
+                || expected.inner == actual.get_type().name()
+        });
+    if !matches {
+        return Err(context::Error::StackMismatch {
+            expected: Box::new(annotation),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+/// Convert an index into a `usize`, checking that it is below `len`
+fn check_index(
+    index: &v::Number,
+    len: usize,
+) -> std::result::Result<usize, context::Error> {
+    let i = index.inner as usize;
+    if i >= len {
+        return Err(context::Error::IndexOutOfRange {
+            index: index.clone(),
+            len,
+        });
+    }
+
+    Ok(i)
+}
+
+fn operator_span<Host>(context: &mut dyn Context<Host>) -> Option<Span> {
+    context.call_stack().operator().span.clone()
+}
+
+fn new_list<Host>(
+    context: &mut dyn Context<Host>,
+    scope: Scope,
+    items: Vec<value::Any>,
+    span: Option<Span>,
+) -> v::List {
+    v::List::new(
+        value::ListInner::from_values(
+            items,
+            context.functions().new_scope(scope, "list"),
+        ),
+        span,
+    )
+}
+
+fn add_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    checked_n(context, "+", u32::checked_add, "too large")
+}
+
+fn sub_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    checked_n(context, "-", u32::checked_sub, "negative")
+}
+
+fn mul_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    checked_n(context, "*", u32::checked_mul, "too large")
+}
+
+fn div_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (a, b) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?;
+
+    if b.inner == 0 {
+        return Err(division_by_zero(b));
+    }
+    let quotient = (a, b).compute::<v::Number, _, _>(|(a, b)| a / b);
+
+    context.stack().push(quotient);
+
+    Ok(())
+}
+
+fn gt_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let is_greater = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?
+        .compute::<v::Bool, _, _>(|(a, b)| a > b);
+
+    context.stack().push(is_greater);
+
+    Ok(())
+}
+
+fn rem_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (a, b) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?;
+
+    if b.inner == 0 {
+        return Err(division_by_zero(b));
+    }
+    let remainder = (a, b).compute::<v::Number, _, _>(|(a, b)| a % b);
+
+    context.stack().push(remainder);
+
+    Ok(())
+}
+
+fn lt_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let is_less = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?
+        .compute::<v::Bool, _, _>(|(a, b)| a < b);
+
+    context.stack().push(is_less);
+
+    Ok(())
+}
+
+fn le_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let is_less_or_equal = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?
+        .compute::<v::Bool, _, _>(|(a, b)| a <= b);
+
+    context.stack().push(is_less_or_equal);
+
+    Ok(())
+}
+
+fn ge_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let is_greater_or_equal = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?
+        .compute::<v::Bool, _, _>(|(a, b)| a >= b);
+
+    context.stack().push(is_greater_or_equal);
+
+    Ok(())
+}
+
+fn min_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let min = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?
+        .compute::<v::Number, _, _>(|(a, b)| a.min(b));
+
+    context.stack().push(min);
+
+    Ok(())
+}
+
+fn max_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let max = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?
+        .compute::<v::Number, _, _>(|(a, b)| a.max(b));
+
+    context.stack().push(max);
+
+    Ok(())
+}
+
+fn pow_n<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (base, exponent) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?;
+
+    let span = base.span.merge(exponent.span);
+    let power = match base.inner.checked_pow(exponent.inner) {
+        Some(power) => v::Number::new(power, span),
+        None => return Err(overflow("pow", "too large", span)),
+    };
+
+    context.stack().push(power);
+
+    Ok(())
+}
+
+/// Apply an operation to two numbers, which fails, if the result doesn't fit
+fn checked_n<Host>(
+    context: &mut dyn Context<Host>,
+    name: &str,
+    operation: fn(u32, u32) -> Option<u32>,
+    problem: &str,
+) -> Result {
+    let (a, b) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Number, t::Number))?;
+
+    let span = a.span.merge(b.span);
+    let result = match operation(a.inner, b.inner) {
+        Some(result) => v::Number::new(result, span),
+        None => return Err(overflow(name, problem, span)),
+    };
+
+    context.stack().push(result);
+
+    Ok(())
+}
+
+fn overflow(name: &str, problem: &str, span: Option<Span>) -> context::Error {
+    context::Error::Arithmetic {
+        message: format!("Result of `{}` is {}", name, problem),
+        span,
+    }
+}
+
+fn to_float<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let number = context.stack().pop::<v::Any>()?.cast(t::Number)?;
+
+    // Large numbers can't all be represented exactly as floats.
+    let float = number.inner as f32;
+    if float as f64 != number.inner as f64 {
+        return Err(context::Error::Conversion {
+            value: Box::new(number.into_any()),
+            target: "float",
+        });
+    }
+
+    context
+        .stack()
+        .push(v::Float::new(R32::from(float), number.span));
+
+    Ok(())
+}
+
+fn add_f<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
 ) -> Result {
-    let quotient = context
+    let (a, b) = context
         .stack()
         .pop::<(_, _)>()?
-        .cast((t::Number, t::Number))?
-        .compute::<v::Number, _, _>(|(a, b)| a / b);
+        .cast((t::Float, t::Float))?;
 
-    context.stack().push(quotient);
+    let span = a.span.merge(b.span);
+    let sum = finite("+", a.inner.into_inner() + b.inner.into_inner(), span)?;
+
+    context.stack().push(sum);
 
     Ok(())
 }
 
-fn gt_n<Host>(
+fn sub_f<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (a, b) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Float, t::Float))?;
+
+    let span = a.span.merge(b.span);
+    let sum = finite("-", a.inner.into_inner() - b.inner.into_inner(), span)?;
+
+    context.stack().push(sum);
+
+    Ok(())
+}
+
+fn mul_f<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (a, b) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Float, t::Float))?;
+
+    let span = a.span.merge(b.span);
+    let product =
+        finite("*", a.inner.into_inner() * b.inner.into_inner(), span)?;
+
+    context.stack().push(product);
+
+    Ok(())
+}
+
+fn gt_f<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
@@ -497,7 +2208,7 @@ fn gt_n<Host>(
     let is_greater = context
         .stack()
         .pop::<(_, _)>()?
-        .cast((t::Number, t::Number))?
+        .cast((t::Float, t::Float))?
         .compute::<v::Bool, _, _>(|(a, b)| a > b);
 
     context.stack().push(is_greater);
@@ -505,70 +2216,262 @@ fn gt_n<Host>(
     Ok(())
 }
 
-fn add_f<Host>(
+fn div_f<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (a, b) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Float, t::Float))?;
+
+    let span = a.span.merge(b.span);
+    let quotient =
+        finite("/", a.inner.into_inner() / b.inner.into_inner(), span)?;
+
+    context.stack().push(quotient);
+
+    Ok(())
+}
+
+fn rem_f<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (a, b) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Float, t::Float))?;
+
+    let span = a.span.merge(b.span);
+    let remainder =
+        finite("%", a.inner.into_inner() % b.inner.into_inner(), span)?;
+
+    context.stack().push(remainder);
+
+    Ok(())
+}
+
+fn lt_f<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let sum = context
+    let is_less = context
         .stack()
         .pop::<(_, _)>()?
         .cast((t::Float, t::Float))?
-        .compute::<v::Float, _, _>(|(a, b)| a + b);
+        .compute::<v::Bool, _, _>(|(a, b)| a < b);
 
-    context.stack().push(sum);
+    context.stack().push(is_less);
 
     Ok(())
 }
 
-fn sub_f<Host>(
+fn le_f<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let sum = context
+    let is_less_or_equal = context
         .stack()
         .pop::<(_, _)>()?
         .cast((t::Float, t::Float))?
-        .compute::<v::Float, _, _>(|(a, b)| a - b);
+        .compute::<v::Bool, _, _>(|(a, b)| a <= b);
 
-    context.stack().push(sum);
+    context.stack().push(is_less_or_equal);
 
     Ok(())
 }
 
-fn mul_f<Host>(
+fn ge_f<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let product = context
+    let is_greater_or_equal = context
         .stack()
         .pop::<(_, _)>()?
         .cast((t::Float, t::Float))?
-        .compute::<v::Float, _, _>(|(a, b)| a * b);
+        .compute::<v::Bool, _, _>(|(a, b)| a >= b);
 
-    context.stack().push(product);
+    context.stack().push(is_greater_or_equal);
 
     Ok(())
 }
 
-fn gt_f<Host>(
+fn min_f<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
     _: Scope,
 ) -> Result {
-    let is_greater = context
+    let min = context
         .stack()
         .pop::<(_, _)>()?
         .cast((t::Float, t::Float))?
-        .compute::<v::Bool, _, _>(|(a, b)| a > b);
+        .compute::<v::Float, _, _>(|(a, b)| a.min(b));
 
-    context.stack().push(is_greater);
+    context.stack().push(min);
+
+    Ok(())
+}
+
+fn max_f<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let max = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Float, t::Float))?
+        .compute::<v::Float, _, _>(|(a, b)| a.max(b));
+
+    context.stack().push(max);
+
+    Ok(())
+}
+
+fn pow_f<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (base, exponent) = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Float, t::Float))?;
+
+    let span = base.span.merge(exponent.span);
+    let power = base.inner.into_inner().powf(exponent.inner.into_inner());
+    let power = finite("pow", power, span)?;
+
+    context.stack().push(power);
+
+    Ok(())
+}
+
+fn abs_f<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let float = context.stack().pop::<v::Any>()?.cast(t::Float)?;
+
+    let abs = finite("abs", float.inner.into_inner().abs(), float.span)?;
+
+    context.stack().push(abs);
+
+    Ok(())
+}
+
+fn sqrt<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let float = context.stack().pop::<v::Any>()?.cast(t::Float)?;
+
+    let root = finite("sqrt", float.inner.into_inner().sqrt(), float.span)?;
+
+    context.stack().push(root);
+
+    Ok(())
+}
+
+fn floor<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let float = context.stack().pop::<v::Any>()?.cast(t::Float)?;
+
+    let floor = finite("floor", float.inner.into_inner().floor(), float.span)?;
+
+    context.stack().push(floor);
+
+    Ok(())
+}
+
+fn ceil<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let float = context.stack().pop::<v::Any>()?.cast(t::Float)?;
+
+    let ceil = finite("ceil", float.inner.into_inner().ceil(), float.span)?;
+
+    context.stack().push(ceil);
+
+    Ok(())
+}
+
+fn round<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let float = context.stack().pop::<v::Any>()?.cast(t::Float)?;
+
+    let round = finite("round", float.inner.into_inner().round(), float.span)?;
+
+    context.stack().push(round);
+
+    Ok(())
+}
+
+fn to_number<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let float = context.stack().pop::<v::Any>()?.cast(t::Float)?;
+
+    // `u32::MAX` can't be represented as `f32`. It rounds up to 2^32, which is
+    // out of range.
+    let value = float.inner.into_inner().trunc();
+    if !(0.0..u32::MAX as f32).contains(&value) {
+        return Err(context::Error::Conversion {
+            value: Box::new(float.into_any()),
+            target: "number",
+        });
+    }
+
+    let number = v::Number::new(value as u32, float.span);
+    context.stack().push(number);
 
     Ok(())
 }
 
+fn division_by_zero(divisor: v::Number) -> context::Error {
+    context::Error::Arithmetic {
+        message: String::from("Division by zero"),
+        span: divisor.span,
+    }
+}
+
+/// Fail, if the result of a float operation is infinite or NaN
+fn finite(
+    operation: &str,
+    result: f32,
+    span: Option<Span>,
+) -> std::result::Result<v::Float, context::Error> {
+    if !result.is_finite() {
+        return Err(context::Error::Arithmetic {
+            message: format!(
+                "Result of `{}` is not a finite number ({})",
+                operation, result,
+            ),
+            span,
+        });
+    }
+
+    Ok(v::Float::new(R32::from(result), span))
+}
+
 fn eq<Host>(_: &mut Host, context: &mut dyn Context<Host>, _: Scope) -> Result {
     let is_equal = context
         .stack()
@@ -580,6 +2483,23 @@ fn eq<Host>(_: &mut Host, context: &mut dyn Context<Host>, _: Scope) -> Result {
     Ok(())
 }
 
+fn assert_eq<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (actual, expected) = context.stack().pop::<(v::Any, v::Any)>()?;
+
+    if actual.kind != expected.kind {
+        return Err(context::Error::AssertEq {
+            expected: Box::new(expected),
+            actual: Box::new(actual),
+        });
+    }
+
+    Ok(())
+}
+
 fn not<Host>(
     _: &mut Host,
     context: &mut dyn Context<Host>,
@@ -595,3 +2515,123 @@ fn not<Host>(
 
     Ok(())
 }
+
+fn and<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let both = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Bool, t::Bool))?
+        .compute::<v::Bool, _, _>(|(a, b)| a && b);
+
+    context.stack().push(both);
+
+    Ok(())
+}
+
+fn or<Host>(_: &mut Host, context: &mut dyn Context<Host>, _: Scope) -> Result {
+    let either = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Bool, t::Bool))?
+        .compute::<v::Bool, _, _>(|(a, b)| a || b);
+
+    context.stack().push(either);
+
+    Ok(())
+}
+
+fn xor<Host>(
+    _: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let exactly_one = context
+        .stack()
+        .pop::<(_, _)>()?
+        .cast((t::Bool, t::Bool))?
+        .compute::<v::Bool, _, _>(|(a, b)| a != b);
+
+    context.stack().push(exactly_one);
+
+    Ok(())
+}
+
+/// Evaluate the second condition, only if the first one is true
+fn and_then<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (a, b) = context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    let span =
+        operator_span(context).merge(a.span.clone().merge(b.span.clone()));
+    let result = evaluate_condition(host, context, a)?
+        && evaluate_condition(host, context, b)?;
+    context.stack().push(v::Bool::new(result, span));
+
+    Ok(())
+}
+
+/// Evaluate the second condition, only if the first one is false
+fn or_else<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let (a, b) = context.stack().pop::<(_, _)>()?.cast((t::List, t::List))?;
+
+    let span =
+        operator_span(context).merge(a.span.clone().merge(b.span.clone()));
+    let result = evaluate_condition(host, context, a)?
+        || evaluate_condition(host, context, b)?;
+    context.stack().push(v::Bool::new(result, span));
+
+    Ok(())
+}
+
+/// Evaluate a list of conditions, until one of them is false
+fn all<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let conditions = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    let span = operator_span(context).merge(conditions.span);
+    let mut result = true;
+    for condition in conditions.inner.items {
+        if !evaluate_condition(host, context, condition.cast(t::List)?)? {
+            result = false;
+            break;
+        }
+    }
+    context.stack().push(v::Bool::new(result, span));
+
+    Ok(())
+}
+
+/// Evaluate a list of conditions, until one of them is true
+fn any<Host>(
+    host: &mut Host,
+    context: &mut dyn Context<Host>,
+    _: Scope,
+) -> Result {
+    let conditions = context.stack().pop::<v::Any>()?.cast(t::List)?;
+
+    let span = operator_span(context).merge(conditions.span);
+    let mut result = false;
+    for condition in conditions.inner.items {
+        if evaluate_condition(host, context, condition.cast(t::List)?)? {
+            result = true;
+            break;
+        }
+    }
+    context.stack().push(v::Bool::new(result, span));
+
+    Ok(())
+}
@@ -0,0 +1,817 @@
+//! Static checking of programs, without running them
+//!
+//! The checker evaluates a program on a stack of types, instead of values. It
+//! only reports errors that would definitely happen, if the program ran: words
+//! that don't refer to any function, functions that would take more values
+//! than the stack holds, and values of the wrong type. Whenever it can't tell
+//! what a function does to the stack, like for `eval`, it stops making
+//! assumptions about the stack.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+};
+
+use crate::{
+    builtins::{self, Output},
+    functions::{Effect, Functions, Signatures},
+    prelude::*,
+    source::{Expression, ExpressionKind, Span},
+    value::{self, t, types::Type},
+};
+
+pub struct Checker<'r, T> {
+    functions: &'r Functions<T>,
+    modules: &'r HashMap<String, Arc<Vec<Expression>>>,
+
+    definitions: Vec<Definition<'r>>,
+    by_name: HashMap<&'r str, Vec<usize>>,
+
+    /// Whether the program defines functions whose names we can't know
+    dynamic: bool,
+
+    /// The lists that have been checked as code already
+    checked: HashSet<*const Expression>,
+
+    errors: Vec<Error>,
+}
+
+impl<'r, T> Checker<'r, T>
+where
+    T: Clone,
+{
+    /// Create a checker that knows the functions and modules of a template
+    ///
+    /// The functions in the root scope, usually the builtins and the prelude,
+    /// are available to the program. Modules are checked when the program
+    /// imports them.
+    pub fn new(
+        functions: &'r Functions<T>,
+        modules: &'r HashMap<String, Arc<Vec<Expression>>>,
+    ) -> Self {
+        Self {
+            functions,
+            modules,
+
+            definitions: Vec::new(),
+            by_name: HashMap::new(),
+
+            dynamic: false,
+            checked: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Check a program, returning the errors sorted by location
+    pub fn check(mut self, program: &'r [Expression]) -> Vec<Error> {
+        self.scan(program, &mut HashSet::new());
+
+        let mut stack = Stack::new(Bottom::Empty);
+        self.evaluate(program, &mut stack);
+
+        // Functions that are never called can still contain errors.
+        for i in 0..self.definitions.len() {
+            self.infer(i);
+        }
+
+        let position = |error: &Error| {
+            error.span.as_ref().map(|span| {
+                (span.stream_name.clone(), span.start.line, span.start.column)
+            })
+        };
+        self.errors.sort_by_key(position);
+        self.errors.dedup_by(|a, b| a.span == b.span);
+
+        self.errors
+    }
+
+    /// Find all functions the program might define
+    ///
+    /// Only definitions like `[ ... ] :name define` can be followed, in the
+    /// program and in the modules it imports.
+    fn scan(
+        &mut self,
+        expressions: &'r [Expression],
+        modules: &mut HashSet<&'r str>,
+    ) {
+        for (i, expression) in expressions.iter().enumerate() {
+            let previous =
+                |n: usize| i.checked_sub(n).map(|i| &expressions[i].kind);
+
+            match &expression.kind {
+                ExpressionKind::List(items) => self.scan(items, modules),
+                ExpressionKind::Word(word) if word == "define" => {
+                    match (previous(2), previous(1)) {
+                        (
+                            Some(ExpressionKind::List(body)),
+                            Some(ExpressionKind::Symbol(name)),
+                        ) => self.add_definition(name, body),
+                        _ => self.dynamic = true,
+                    }
+                }
+                ExpressionKind::Word(word) if word == "import" => {
+                    match previous(1) {
+                        Some(ExpressionKind::String(name)) => {
+                            let module = self.modules.get_key_value(name);
+                            if let Some((name, module)) = module {
+                                if modules.insert(name) {
+                                    self.scan(module, modules);
+                                }
+                            }
+                        }
+                        _ => self.dynamic = true,
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn add_definition(&mut self, name: &'r str, body: &'r [Expression]) {
+        let effect = match body {
+            [declaration, word, rest @ ..] if is_word(word, "effect") => {
+                let list = value::Any::from_expression(
+                    declaration.clone(),
+                    self.functions.root_scope(),
+                );
+                list.cast(t::List)
+                    .ok()
+                    .and_then(|list| Effect::parse(&list).ok())
+                    .map(|effect| (effect, rest))
+            }
+            _ => None,
+        };
+
+        let definition = match effect {
+            Some((effect, body)) => Definition {
+                body,
+                signature: effect.inputs.clone(),
+                declared: Some(effect),
+                inference: Inference::NotYet,
+            },
+            None => Definition {
+                body,
+                signature: Vec::new(),
+                declared: None,
+                inference: Inference::NotYet,
+            },
+        };
+
+        self.by_name
+            .entry(name)
+            .or_default()
+            .push(self.definitions.len());
+        self.definitions.push(definition);
+    }
+
+    fn evaluate(
+        &mut self,
+        expressions: &'r [Expression],
+        stack: &mut Stack<'r>,
+    ) {
+        for expression in expressions {
+            let ty: &'static dyn Type = match &expression.kind {
+                ExpressionKind::Word(word) => {
+                    self.call(word, expression.span.as_ref(), stack);
+                    continue;
+                }
+                ExpressionKind::List(_) => {
+                    stack.items.push(Item::List(expression));
+                    continue;
+                }
+                ExpressionKind::Bool(_) => &t::Bool,
+                ExpressionKind::Float(_) => &t::Float,
+                ExpressionKind::Number(_) => &t::Number,
+                ExpressionKind::String(_) => &t::String,
+                ExpressionKind::Symbol(_) => &t::Symbol,
+            };
+
+            stack.items.push(Item::Type(ty));
+        }
+    }
+
+    fn call(
+        &mut self,
+        word: &'r str,
+        span: Option<&Span>,
+        stack: &mut Stack<'r>,
+    ) {
+        let error = |kind| Error {
+            kind,
+            span: span.cloned(),
+        };
+
+        if word == "on_stack" {
+            if let Some(Item::List(annotation)) = stack.items.last() {
+                let annotation = *annotation;
+                stack.items.pop();
+                if let Err(kind) = check_annotation(annotation, stack) {
+                    self.errors.push(error(kind));
+                    stack.forget();
+                }
+                return;
+            }
+        }
+
+        let mut candidates = Vec::new();
+        for signature in
+            self.functions.signatures(self.functions.root_scope(), word)
+        {
+            // The prelude defines `import` without arguments, but it takes
+            // the name of a module, and leaves nothing.
+            let (signature, effect) = match word {
+                "import" if signature.is_empty() => {
+                    let effect = Known {
+                        consumed: 1,
+                        outputs: Vec::new(),
+                    };
+                    (vec![&t::String as _], Some(effect))
+                }
+                _ => {
+                    let effect = builtin_effect(word, &signature);
+                    (signature, effect)
+                }
+            };
+
+            let code = code_args(word, signature.len());
+            candidates.push(Candidate {
+                signature,
+                effect,
+                code,
+            });
+        }
+        let definitions = self.by_name.get(word).cloned().unwrap_or_default();
+        for i in definitions {
+            let effect = self.infer(i);
+            candidates.push(Candidate {
+                signature: self.definitions[i].signature.clone(),
+                effect,
+                code: &[],
+            });
+        }
+
+        if candidates.is_empty() {
+            if !self.dynamic {
+                self.errors.push(error(ErrorKind::UnknownWord(word.into())));
+            }
+            stack.forget();
+            return;
+        }
+
+        let matches: Vec<_> = candidates
+            .iter()
+            .map(|candidate| (candidate, candidate.matches(stack)))
+            .collect();
+        let matching: Vec<_> = matches
+            .iter()
+            .filter(|(_, result)| *result == Match::Yes)
+            .map(|(candidate, _)| *candidate)
+            .collect();
+
+        if matching.is_empty() {
+            // If the values on the stack fit a function, there just aren't
+            // enough of them.
+            let fitting = matches
+                .iter()
+                .filter(|(_, result)| *result == Match::Depth)
+                .map(|(candidate, _)| candidate.depth())
+                .min();
+
+            let kind = match fitting {
+                Some(needed) => ErrorKind::Underflow {
+                    word: word.into(),
+                    needed,
+                    available: stack.items.len(),
+                },
+                None => {
+                    let depth = candidates
+                        .iter()
+                        .map(|candidate| candidate.signature.len())
+                        .max()
+                        .unwrap_or(0);
+                    ErrorKind::TypeMismatch {
+                        word: word.into(),
+                        expected: candidates
+                            .iter()
+                            .map(|candidate| candidate.signature.clone())
+                            .collect(),
+                        found: stack.types(depth),
+                    }
+                }
+            };
+
+            self.errors.push(error(kind));
+            stack.forget();
+            return;
+        }
+
+        let candidate = match matching.as_slice() {
+            [candidate] => *candidate,
+            _ => {
+                stack.forget();
+                return;
+            }
+        };
+
+        let depth = candidate.depth();
+        let args: Vec<_> = (0..depth).map(|_| stack.pop()).collect();
+
+        // Lists that a function evaluates are code, and can be checked like
+        // the program itself.
+        for &i in candidate.code {
+            if let Some(Item::List(list)) = args.get(depth - 1 - i) {
+                self.check_code(list);
+            }
+        }
+
+        match &candidate.effect {
+            Some(effect) => {
+                for output in &effect.outputs {
+                    let item = match output {
+                        Item::Input(i) => args[*i].clone(),
+                        item => item.clone(),
+                    };
+                    stack.items.push(item);
+                }
+            }
+            None => stack.forget(),
+        }
+    }
+
+    /// Check a list that is evaluated, like the body of an `if`
+    fn check_code(&mut self, list: &'r Expression) {
+        if !self.checked.insert(list) {
+            return;
+        }
+
+        if let ExpressionKind::List(items) = &list.kind {
+            self.evaluate(items, &mut Stack::new(Bottom::Unknown));
+        }
+    }
+
+    /// Find out what a function does to the stack, checking its body
+    ///
+    /// Returns `None`, if that can't be known.
+    fn infer(&mut self, i: usize) -> Option<Known<'r>> {
+        match &self.definitions[i].inference {
+            Inference::Done(known) => return known.clone(),
+            Inference::InProgress => return None,
+            Inference::NotYet => (),
+        }
+        self.definitions[i].inference = Inference::InProgress;
+
+        let body = self.definitions[i].body;
+        let known = match self.definitions[i].declared.clone() {
+            Some(effect) => {
+                let mut stack = Stack::new(Bottom::Unknown);
+                stack.items =
+                    effect.inputs.iter().map(|&t| Item::Type(t)).collect();
+                self.evaluate(body, &mut stack);
+
+                Some(Known {
+                    consumed: effect.inputs.len(),
+                    outputs: effect
+                        .outputs
+                        .iter()
+                        .map(|&t| Item::Type(t))
+                        .collect(),
+                })
+            }
+            None => {
+                let mut stack = Stack::new(Bottom::Inputs(0));
+                self.evaluate(body, &mut stack);
+
+                match stack.bottom {
+                    Bottom::Inputs(consumed) => Some(Known {
+                        consumed,
+                        outputs: stack.items,
+                    }),
+                    Bottom::Empty | Bottom::Unknown => None,
+                }
+            }
+        };
+
+        self.definitions[i].inference = Inference::Done(known.clone());
+        known
+    }
+}
+
+/// A function that might be defined by the program
+struct Definition<'r> {
+    body: &'r [Expression],
+    signature: Vec<&'static dyn Type>,
+    declared: Option<Effect>,
+    inference: Inference<'r>,
+}
+
+enum Inference<'r> {
+    NotYet,
+    InProgress,
+    Done(Option<Known<'r>>),
+}
+
+/// What a function does to the stack
+#[derive(Clone)]
+struct Known<'r> {
+    /// How many values the function takes from the stack
+    consumed: usize,
+
+    /// The values the function leaves on the stack, which can refer to the
+    /// ones it took
+    outputs: Vec<Item<'r>>,
+}
+
+struct Candidate<'r> {
+    signature: Vec<&'static dyn Type>,
+    effect: Option<Known<'r>>,
+
+    /// The arguments that are evaluated, in stack order
+    code: &'static [usize],
+}
+
+impl Candidate<'_> {
+    /// How many values the function takes from the stack
+    fn depth(&self) -> usize {
+        let consumed = self.effect.as_ref().map_or(0, |effect| effect.consumed);
+        self.signature.len().max(consumed)
+    }
+
+    fn matches(&self, stack: &Stack) -> Match {
+        for i in 0..self.depth() {
+            let item = match stack.peek(i) {
+                Some(item) => item,
+                None => return Match::Depth,
+            };
+
+            let expected = match self.signature.len().checked_sub(i + 1) {
+                Some(j) => self.signature[j],
+                None => continue,
+            };
+            if let Some(actual) = item.ty() {
+                if *expected != *actual {
+                    return Match::Type;
+                }
+            }
+        }
+
+        Match::Yes
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Match {
+    Yes,
+    Depth,
+    Type,
+}
+
+struct Stack<'r> {
+    items: Vec<Item<'r>>,
+    bottom: Bottom,
+}
+
+/// What we know about the values below the items on the stack
+#[derive(Clone, Copy)]
+enum Bottom {
+    /// There are none
+    Empty,
+
+    /// There might be any number of values
+    Unknown,
+
+    /// The values are the inputs of a function, whose effect we're inferring.
+    /// Counts how many of them have been taken.
+    Inputs(usize),
+}
+
+impl<'r> Stack<'r> {
+    fn new(bottom: Bottom) -> Self {
+        Self {
+            items: Vec::new(),
+            bottom,
+        }
+    }
+
+    /// The item at the given depth, counted from the top
+    ///
+    /// Returns `None`, if there definitely is no such item.
+    fn peek(&self, depth: usize) -> Option<&Item<'r>> {
+        match self.items.len().checked_sub(depth + 1) {
+            Some(i) => Some(&self.items[i]),
+            None => match self.bottom {
+                Bottom::Empty => None,
+                Bottom::Unknown | Bottom::Inputs(_) => Some(&Item::Unknown),
+            },
+        }
+    }
+
+    fn pop(&mut self) -> Item<'r> {
+        if let Some(item) = self.items.pop() {
+            return item;
+        }
+
+        match &mut self.bottom {
+            Bottom::Empty | Bottom::Unknown => Item::Unknown,
+            Bottom::Inputs(taken) => {
+                *taken += 1;
+                Item::Input(*taken - 1)
+            }
+        }
+    }
+
+    /// The names of the types on top of the stack, in stack order
+    fn types(&self, depth: usize) -> Vec<&'static str> {
+        let mut types: Vec<_> = (0..depth)
+            .map_while(|i| self.peek(i))
+            .map(|item| item.ty().map_or("any", |t| t.name()))
+            .collect();
+        types.reverse();
+        types
+    }
+
+    /// Stop making assumptions about the stack
+    fn forget(&mut self) {
+        self.items.clear();
+        self.bottom = Bottom::Unknown;
+    }
+}
+
+/// A value on the checker's stack
+#[derive(Clone)]
+enum Item<'r> {
+    /// A value we know nothing about
+    Unknown,
+
+    /// An input of the function whose effect is inferred, counted from the
+    /// top of the stack
+    Input(usize),
+
+    /// A value of which only the type is known
+    Type(&'static dyn Type),
+
+    /// A list literal, which might be evaluated later
+    List(&'r Expression),
+}
+
+impl Item<'_> {
+    fn ty(&self) -> Option<&'static dyn Type> {
+        match self {
+            Item::Unknown | Item::Input(_) => None,
+            Item::Type(t) => Some(*t),
+            Item::List(_) => Some(&t::List),
+        }
+    }
+}
+
+/// Check the stack against an annotation, like `[: string number :]`
+///
+/// Afterwards, the annotated values are known to be of those types.
+fn check_annotation(
+    annotation: &Expression,
+    stack: &mut Stack,
+) -> Result<(), ErrorKind> {
+    let words = match &annotation.kind {
+        ExpressionKind::List(words) => words,
+        _ => return Ok(()),
+    };
+
+    let mut expected = Vec::new();
+    for word in words {
+        match &word.kind {
+            ExpressionKind::Word(name) => match t::from_name(name) {
+                Some(t) => expected.push(t),
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        }
+    }
+
+    let found = stack.types(expected.len());
+    let matches =
+        found.len() == expected.len()
+            && expected.iter().rev().enumerate().all(|(i, &expected)| {
+                match stack.peek(i).and_then(Item::ty) {
+                    Some(actual) => *expected == *actual,
+                    None => true,
+                }
+            });
+    if !matches {
+        return Err(ErrorKind::StackMismatch {
+            expected: expected.iter().map(|t| t.name()).collect(),
+            found,
+        });
+    }
+
+    for (i, &t) in expected.iter().rev().enumerate() {
+        let i = stack.items.len().checked_sub(i + 1);
+        if let Some(item) = i.map(|i| &mut stack.items[i]) {
+            if item.ty().is_none() {
+                *item = Item::Type(t);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What a builtin function does to the stack, as declared in [`builtins`]
+///
+/// Returns `None` for functions whose effect depends on the values they are
+/// called with, like `eval`.
+fn builtin_effect<'r>(
+    name: &str,
+    signature: &[&'static dyn Type],
+) -> Option<Known<'r>> {
+    let outputs = builtins::outputs(name, signature)?
+        .iter()
+        .map(|output| match *output {
+            Output::Input(i) => Some(Item::Input(i)),
+            Output::Type(t) => Some(Item::Type(t)),
+            Output::Same => signature.first().map(|&t| Item::Type(t)),
+            Output::Unknown => Some(Item::Unknown),
+        })
+        .collect::<Option<_>>()?;
+
+    Some(Known {
+        consumed: signature.len(),
+        outputs,
+    })
+}
+
+/// The arguments of a builtin function that it evaluates, in stack order
+///
+/// The body of `try` is left out, as errors there are expected.
+fn code_args(name: &str, arity: usize) -> &'static [usize] {
+    match (name, arity) {
+        ("eval" | "loop", 1) => &[0],
+        ("try", 2) => &[1],
+        ("if" | "while" | "and_then" | "or_else", 2) => &[0, 1],
+        ("if_else", 3) => &[0, 1, 2],
+        ("map" | "filter" | "sort_by" | "find" | "dig", 2) => &[1],
+        ("reduce", 3) => &[2],
+        ("test", 2) => &[0],
+        _ => &[],
+    }
+}
+
+fn is_word(expression: &Expression, name: &str) -> bool {
+    matches!(&expression.kind, ExpressionKind::Word(word) if word == name)
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    UnknownWord(String),
+    Underflow {
+        word: String,
+        needed: usize,
+        available: usize,
+    },
+    TypeMismatch {
+        word: String,
+        expected: Signatures,
+        found: Vec<&'static str>,
+    },
+    StackMismatch {
+        expected: Vec<&'static str>,
+        found: Vec<&'static str>,
+    },
+}
+
+impl Error {
+    /// A short name that identifies the kind of error, like `unknown_word`
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            ErrorKind::UnknownWord(_) => "unknown_word",
+            ErrorKind::Underflow { .. } => "stack_underflow",
+            ErrorKind::TypeMismatch { .. } => "type_mismatch",
+            ErrorKind::StackMismatch { .. } => "stack_mismatch",
+        }
+    }
+
+    pub fn spans<'r>(&'r self, spans: &mut Vec<&'r Span>) {
+        spans.extend(self.span.as_ref());
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ErrorKind::UnknownWord(word) => {
+                write!(
+                    f,
+                    "Unknown word: `{}` doesn't refer to a function",
+                    word
+                )
+            }
+            ErrorKind::Underflow {
+                word,
+                needed,
+                available,
+            } => {
+                write!(
+                    f,
+                    "Stack underflow: `{}` takes {} value(s), but the stack \
+                    only holds {}",
+                    word, needed, available,
+                )
+            }
+            ErrorKind::TypeMismatch {
+                word,
+                expected,
+                found,
+            } => {
+                let expected: Vec<_> = expected
+                    .iter()
+                    .map(|signature| {
+                        let types: Vec<_> =
+                            signature.iter().map(|t| t.name()).collect();
+                        format!("`{}`", types.join(" "))
+                    })
+                    .collect();
+
+                write!(
+                    f,
+                    "Type mismatch: `{}` takes {}, but would find `{}`",
+                    word,
+                    expected.join(" or "),
+                    found.join(" "),
+                )
+            }
+            ErrorKind::StackMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Stack doesn't match annotation: expected `{}`, but would \
+                    find `{}`",
+                    expected.join(" "),
+                    found.join(" "),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        builtins::builtins,
+        functions::{Function, Functions},
+        pipeline::Pipeline,
+    };
+
+    use super::Checker;
+
+    fn check(program: &str) -> Vec<&'static str> {
+        let mut functions = Functions::<Function<()>>::new();
+        builtins(&mut functions);
+        let modules = HashMap::new();
+
+        let expressions = Pipeline::new("test".into(), program.as_bytes())
+            .parser
+            .expressions(&mut String::new())
+            .unwrap();
+
+        Checker::new(&functions, &modules)
+            .check(&expressions)
+            .iter()
+            .map(|error| error.name())
+            .collect()
+    }
+
+    #[test]
+    fn checker_should_find_definite_errors() {
+        assert_eq!(check("1 +\n"), ["stack_underflow"]);
+        assert_eq!(check("1 \"a\" +\n"), ["type_mismatch"]);
+        assert_eq!(check("\"a\" upper 1 swap +\n"), ["type_mismatch"]);
+        assert_eq!(check("a\n"), ["unknown_word"]);
+        assert_eq!(check("[ a ] [ true ] if\n"), ["unknown_word"]);
+        assert_eq!(check("1 [: string :]\n"), ["stack_mismatch"]);
+        assert_eq!(
+            check("[ ( number -- ) drop ] :f define :a f\n"),
+            ["type_mismatch"]
+        );
+    }
+
+    #[test]
+    fn checker_should_infer_the_effects_of_functions() {
+        assert_eq!(check("[ clone ] :f define 1 f + drop\n"), [""; 0]);
+        assert_eq!(check("[ drop ] :f define f\n"), ["stack_underflow"]);
+        assert_eq!(check("[ clone ] :f define \"a\" f +\n"), ["type_mismatch"]);
+        assert_eq!(check("[ 1 f ] :f define\n"), [""; 0]);
+    }
+
+    #[test]
+    fn checker_should_not_make_assumptions_it_cant_verify() {
+        assert_eq!(check("[ 1 2 ] eval + drop\n"), [""; 0]);
+        assert_eq!(check("[ 1 \"a\" + ] [ drop ] try\n"), [""; 0]);
+        assert_eq!(check("[ ] :f caller define a\n"), [""; 0]);
+    }
+}
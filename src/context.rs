@@ -1,14 +1,21 @@
-use std::{fmt, io};
+use std::{fmt, io, rc::Rc};
 
 use termion::{color, style};
 
 use crate::{
     call_stack::CallStack,
-    functions::{self, Function, Functions, Scope, Signatures},
+    format,
+    functions::{self, Effect, Function, Functions, Scope, Signatures},
     pipeline::parser,
     source::Span,
     stack::{self, Stack},
-    value::{self, cast::TypeError, v},
+    test_case::TestCase,
+    value::{
+        self,
+        cast::TypeError,
+        types::{Type, Typed as _},
+        v, Value as _,
+    },
 };
 
 pub trait Context<Host> {
@@ -18,11 +25,24 @@ pub trait Context<Host> {
 
     fn call_stack(&mut self) -> &mut CallStack;
 
+    fn test_cases(&mut self) -> &mut Vec<TestCase>;
+
     fn output(&mut self) -> &mut dyn io::Write;
 
     fn load(&mut self, name: v::String, scope: Scope)
         -> Result<v::List, Error>;
 
+    fn define(
+        &mut self,
+        scope: Scope,
+        name: String,
+        args: &[&'static dyn Type],
+        function: Function<Host>,
+    ) -> Result<(), functions::DefineError> {
+        self.functions().define(scope, name, args, function)?;
+        Ok(())
+    }
+
     fn evaluate_value(
         &mut self,
         host: &mut Host,
@@ -39,30 +59,155 @@ pub trait Context<Host> {
 
 #[derive(Debug)]
 pub enum Error {
+    Arithmetic {
+        message: String,
+        span: Option<Span>,
+    },
+    AssertEq {
+        expected: Box<value::Any>,
+        actual: Box<value::Any>,
+    },
+    Break,
     Caller,
+    Conversion {
+        value: Box<value::Any>,
+        target: &'static str,
+    },
     DefineFunction(functions::DefineError),
-    Failure,
+    Failure(Option<value::Any>),
+    FieldNotFound {
+        kind: String,
+        name: Box<v::Symbol>,
+    },
+    FieldWithoutValue(v::Symbol),
+    Format {
+        error: format::Error,
+        span: Option<Span>,
+    },
     FunctionNotFound {
         name: String,
         stack: Stack,
         candidates: Signatures,
         scope: String,
     },
+    IndexOutOfRange {
+        index: v::Number,
+        len: usize,
+    },
+    InvalidCase(value::Any),
+    InvalidEffect {
+        message: String,
+        span: Option<Span>,
+    },
     ModuleNotFound(String),
     Io(io::Error),
     Parser(parser::Error),
     Stack(stack::Error),
+    StackEffect {
+        name: String,
+        effect: Rc<Effect>,
+        consumed: usize,
+        left: Vec<value::Any>,
+        definition: Option<Box<Span>>,
+    },
+    StackMismatch {
+        expected: Box<v::List>,
+        actual: Vec<value::Any>,
+    },
     Type(TypeError),
 }
 
 impl Error {
+    /// A short name that identifies the kind of error, like `type`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Error::Arithmetic { .. } => "arithmetic",
+            Error::AssertEq { .. } => "assert_eq",
+            Error::Break => "break",
+            Error::Caller => "caller",
+            Error::Conversion { .. } => "conversion",
+            Error::DefineFunction(_) => "define_function",
+            Error::Failure(_) => "failure",
+            Error::FieldNotFound { .. } => "field_not_found",
+            Error::FieldWithoutValue(_) => "field_without_value",
+            Error::Format { .. } => "format",
+            Error::FunctionNotFound { .. } => "function_not_found",
+            Error::IndexOutOfRange { .. } => "index_out_of_range",
+            Error::InvalidCase(_) => "invalid_case",
+            Error::InvalidEffect { .. } => "invalid_effect",
+            Error::ModuleNotFound(_) => "module_not_found",
+            Error::Io(_) => "io",
+            Error::Parser(_) => "parser",
+            Error::Stack(_) => "stack",
+            Error::StackEffect { .. } => "stack_effect",
+            Error::StackMismatch { .. } => "stack_mismatch",
+            Error::Type(_) => "type",
+        }
+    }
+
+    /// Convert the error into a value that can be handled by Kari code
+    ///
+    /// Values passed to `fail_with` are returned as they are, if they are errors.
+    /// Everything else is converted into an error, with the name of the error
+    /// as its kind.
+    pub fn into_value(self, span: Option<Span>) -> value::Any {
+        let (kind, fields) = match self {
+            Error::Failure(None) => (String::from("failure"), Vec::new()),
+            Error::Failure(Some(value)) => match value.kind {
+                value::Kind::Error(_) => return value,
+                _ => (String::from("failure"), vec![("value".into(), value)]),
+            },
+            error => {
+                let message = value::Any::new(
+                    value::Kind::String(error.to_string()),
+                    span.clone(),
+                );
+                (error.name().into(), vec![("message".into(), message)])
+            }
+        };
+
+        value::Any::new(
+            value::Kind::Error(Box::new(value::ErrorInner { kind, fields })),
+            span,
+        )
+    }
+
     pub fn spans<'r>(&'r self, spans: &mut Vec<&'r Span>) {
         match self {
+            Error::Arithmetic { span, .. } => spans.extend(span.as_ref()),
+            Error::AssertEq { expected, actual } => {
+                spans.extend(actual.span.as_ref());
+                spans.extend(expected.span.as_ref());
+            }
+            Error::Break => (),
             Error::Caller => (),
+            Error::Conversion { value, .. } => {
+                spans.extend(value.span.as_ref())
+            }
             Error::DefineFunction(_) => (),
-            Error::Failure => (),
+            Error::Failure(_) => (),
+            Error::FieldNotFound { name, .. } => {
+                spans.extend(name.span.as_ref())
+            }
+            Error::FieldWithoutValue(name) => spans.extend(name.span.as_ref()),
+            Error::Format { span, .. } => spans.extend(span.as_ref()),
             Error::FunctionNotFound { .. } => (),
+            Error::IndexOutOfRange { index, .. } => {
+                spans.extend(index.span.as_ref())
+            }
+            Error::InvalidCase(case) => spans.extend(case.span.as_ref()),
+            Error::InvalidEffect { span, .. } => spans.extend(span.as_ref()),
             Error::ModuleNotFound(_) => (),
+            Error::StackEffect {
+                left, definition, ..
+            } => {
+                spans.extend(definition.as_deref());
+                spans.extend(left.iter().filter_map(|v| v.span.as_ref()));
+            }
+            Error::StackMismatch { expected, actual } => {
+                spans.extend(expected.span.as_ref());
+                spans.extend(actual.iter().filter_map(|v| v.span.as_ref()));
+            }
 
             Error::Parser(error) => error.spans(spans),
             Error::Stack(error) => error.spans(spans),
@@ -74,6 +219,87 @@ impl Error {
 
     pub fn write_hint(&self, stderr: &mut dyn io::Write) -> io::Result<()> {
         match self {
+            Error::AssertEq { expected, actual } => {
+                for (label, value) in
+                    [("Expected", expected), ("Actual", actual)]
+                {
+                    writeln!(
+                        stderr,
+                        "{}{}:{}",
+                        color::Fg(color::Cyan),
+                        label,
+                        color::Fg(color::Reset),
+                    )?;
+                    write!(
+                        stderr,
+                        "    {}{}{}{}{}\n\n",
+                        style::Bold,
+                        color::Fg(color::LightWhite),
+                        value.kind,
+                        color::Fg(color::Reset),
+                        style::Reset,
+                    )?;
+                }
+
+                let mut differences = Vec::new();
+                diff(
+                    &expected.kind,
+                    &actual.kind,
+                    &mut String::new(),
+                    &mut differences,
+                );
+
+                // A single difference at the top level is just a repetition
+                // of the above.
+                let is_nested = differences
+                    .first()
+                    .is_some_and(|difference| difference.starts_with("at"));
+                if differences.len() > 1 || is_nested {
+                    writeln!(
+                        stderr,
+                        "{}Differences:{}",
+                        color::Fg(color::Cyan),
+                        color::Fg(color::Reset),
+                    )?;
+                    for difference in differences {
+                        writeln!(stderr, "    {}", difference)?;
+                    }
+                }
+
+                Ok(())
+            }
+            Error::Failure(value) => {
+                let error = match value.as_ref().map(|value| &value.kind) {
+                    Some(value::Kind::Error(error))
+                        if !error.fields.is_empty() =>
+                    {
+                        error
+                    }
+                    _ => return Ok(()),
+                };
+
+                writeln!(
+                    stderr,
+                    "{}Fields:{}",
+                    color::Fg(color::Cyan),
+                    color::Fg(color::Reset),
+                )?;
+                for (name, value) in &error.fields {
+                    writeln!(
+                        stderr,
+                        "    {}: {}{}{}{}{}",
+                        name,
+                        style::Bold,
+                        color::Fg(color::LightWhite),
+                        value.kind,
+                        color::Fg(color::Reset),
+                        style::Reset,
+                    )?;
+                }
+                writeln!(stderr)?;
+
+                Ok(())
+            }
             Error::FunctionNotFound {
                 stack,
                 candidates,
@@ -134,6 +360,64 @@ impl Error {
     }
 }
 
+/// Describe the differences between two values
+///
+/// Lists are compared item by item, so the differences within nested lists
+/// can be described precisely. `path` is the location of the values within
+/// the outermost lists.
+fn diff(
+    expected: &value::Kind,
+    actual: &value::Kind,
+    path: &mut String,
+    differences: &mut Vec<String>,
+) {
+    use value::Kind;
+
+    if expected == actual {
+        return;
+    }
+
+    let location = if path.is_empty() {
+        String::new()
+    } else {
+        format!("at {}: ", path)
+    };
+
+    let (expected, actual) = match (expected, actual) {
+        (Kind::List(expected), Kind::List(actual)) => (expected, actual),
+        (expected, actual) => {
+            differences.push(format!(
+                "{}expected `{}`, found `{}`",
+                location, expected, actual,
+            ));
+            return;
+        }
+    };
+
+    let len = expected.items.len().max(actual.items.len());
+    for i in 0..len {
+        let len_before = path.len();
+        path.push_str(&format!("[{}]", i));
+
+        match (expected.items.get(i), actual.items.get(i)) {
+            (Some(expected), Some(actual)) => {
+                diff(&expected.kind, &actual.kind, path, differences);
+            }
+            (Some(expected), None) => {
+                differences
+                    .push(format!("at {}: missing `{}`", path, expected.kind));
+            }
+            (None, Some(actual)) => {
+                differences
+                    .push(format!("at {}: unexpected `{}`", path, actual.kind));
+            }
+            (None, None) => {}
+        }
+
+        path.truncate(len_before);
+    }
+}
+
 impl From<stack::Error> for Error {
     fn from(from: stack::Error) -> Self {
         Error::Stack(from)
@@ -167,21 +451,116 @@ impl From<functions::DefineError> for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Error::Arithmetic { message, .. } => {
+                write!(f, "Arithmetic error: {}", message)
+            }
+            Error::AssertEq { .. } => {
+                write!(f, "Assertion failed: Values are not equal")
+            }
+            Error::Break => {
+                write!(f, "`break` outside of a loop")
+            }
             Error::Caller => {
                 write!(f, "No caller found")
             }
-            Error::Failure { .. } => {
-                write!(f, "Explicit failure")
+            Error::Conversion { value, target } => {
+                write!(f, "Can't convert `{}` to {}", value.kind, target)
+            }
+            Error::Failure(None) => write!(f, "Explicit failure"),
+            Error::Failure(Some(value)) => match &value.kind {
+                value::Kind::String(message) => {
+                    write!(f, "Failure: {}", message)
+                }
+                value::Kind::Error(error) => {
+                    write!(f, "Failure: `{}`", error.kind)
+                }
+                kind => write!(f, "Failure: `{}`", kind),
+            },
+            Error::FieldNotFound { kind, name } => {
+                write!(f, "Error `{}` has no field `{}`", kind, name.inner)
+            }
+            Error::FieldWithoutValue(name) => {
+                write!(f, "No value for error field `{}`", name.inner)
+            }
+            Error::Format { error, .. } => {
+                write!(f, "Format error: {}", error)
             }
             Error::FunctionNotFound { name, .. } => {
                 write!(f, "No matching function found: `{}`", name)
             }
+            Error::IndexOutOfRange { index, len } => {
+                write!(
+                    f,
+                    "Index out of range: Index is {}, but length is {}",
+                    index.inner, len,
+                )
+            }
+            Error::InvalidCase(case) => {
+                write!(
+                    f,
+                    "Expected `[ condition body ]`, found `{}`",
+                    case.kind,
+                )
+            }
+            Error::InvalidEffect { message, .. } => {
+                write!(f, "Invalid stack effect: {}", message)
+            }
             Error::ModuleNotFound(name) => {
                 write!(f, "Module not found: {}", name)
             }
             Error::Io(error) => {
                 write!(f, "Error loading stream: {}", error)
             }
+            Error::StackEffect {
+                name,
+                effect,
+                consumed,
+                left,
+                ..
+            } => {
+                write!(
+                    f,
+                    "`{}` doesn't match its stack effect `{}`: ",
+                    name, effect,
+                )?;
+
+                if *consumed > 0 {
+                    return write!(
+                        f,
+                        "It consumed {} more value(s) than declared",
+                        consumed,
+                    );
+                }
+                if left.len() != effect.outputs.len() {
+                    return write!(
+                        f,
+                        "It left {} value(s) instead of {}",
+                        left.len(),
+                        effect.outputs.len(),
+                    );
+                }
+
+                let left: Vec<_> =
+                    left.iter().map(|v| v.get_type().name()).collect();
+                write!(f, "It left `{}`", left.join(" "))
+            }
+            Error::StackMismatch { expected, actual } => {
+                let expected: Vec<_> = expected
+                    .inner
+                    .items
+                    .iter()
+                    .map(|item| item.kind.to_string())
+                    .collect();
+                let actual: Vec<_> =
+                    actual.iter().map(|v| v.get_type().name()).collect();
+
+                write!(
+                    f,
+                    "Stack doesn't match annotation: expected `{}`, found `{}`",
+                    expected.join(" "),
+                    actual.join(" "),
+                )
+            }
 
             Error::DefineFunction(error) => error.fmt(f),
             Error::Parser(error) => error.fmt(f),
@@ -190,3 +569,47 @@ impl fmt::Display for Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        functions::Functions,
+        value::{self, Kind},
+    };
+
+    use super::diff;
+
+    fn list(items: Vec<Kind>) -> Kind {
+        let scope = Functions::<()>::new().root_scope();
+        let items = items
+            .into_iter()
+            .map(|kind| value::Any { kind, span: None })
+            .collect();
+
+        Kind::List(value::ListInner::from_values(items, scope))
+    }
+
+    #[test]
+    fn diff_should_describe_differences_within_nested_lists() {
+        let expected = list(vec![
+            Kind::Number(1),
+            list(vec![Kind::Number(2), Kind::Number(5)]),
+        ]);
+        let actual = list(vec![
+            Kind::Number(1),
+            list(vec![Kind::Number(2), Kind::Number(3)]),
+            Kind::Number(4),
+        ]);
+
+        let mut differences = Vec::new();
+        diff(&expected, &actual, &mut String::new(), &mut differences);
+
+        assert_eq!(
+            differences,
+            vec![
+                "at [1][1]: expected `5`, found `3`",
+                "at [2]: unexpected `4`",
+            ]
+        );
+    }
+}
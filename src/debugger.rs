@@ -0,0 +1,175 @@
+pub mod console;
+
+use std::collections::HashMap;
+
+use crate::{
+    source::{Position, Span},
+    stack::Stack,
+};
+
+pub use self::console::Console;
+
+/// Controls the execution of a program
+///
+/// A debugger can be attached to an interpreter using
+/// [`Interpreter::with_debugger`]. Before each word is evaluated, the
+/// interpreter checks whether it should pause there. If it should, it calls
+/// [`Debugger::pause`], which can inspect the state of the program, and returns
+/// once execution should resume.
+///
+/// [`Interpreter::with_debugger`]: crate::Interpreter::with_debugger
+pub trait Debugger {
+    /// Whether the program should pause before evaluating the word
+    ///
+    /// This is only called, if the program isn't going to pause anyway, due to
+    /// a previous step.
+    fn is_breakpoint(&mut self, word: &str, span: Option<&Span>) -> bool;
+
+    /// Whether the program should pause before evaluating its first word
+    fn stop_on_entry(&self) -> bool {
+        false
+    }
+
+    /// Called when the program pauses
+    ///
+    /// Execution resumes, once this method returns.
+    fn pause(&mut self, pause: Pause) -> Resume;
+}
+
+/// The state of a paused program
+pub struct Pause<'r> {
+    /// The word that is about to be evaluated
+    pub word: &'r str,
+
+    /// The location of the word in the source code
+    pub span: Option<&'r Span>,
+
+    pub stack: &'r Stack,
+
+    /// The frames on the call stack, with the innermost frame last
+    pub frames: Vec<Frame<'r>>,
+
+    /// The source code of all loaded streams, indexed by stream name
+    pub sources: &'r HashMap<String, String>,
+}
+
+impl Pause<'_> {
+    /// Returns the line of source code that the word is on
+    pub fn source_line(&self) -> Option<&str> {
+        let span = self.span?;
+        let source = self.sources.get(&span.stream_name)?;
+
+        source.lines().nth(span.start.line)
+    }
+}
+
+pub struct Frame<'r> {
+    /// The name of the scope that the frame's word was evaluated in
+    pub scope: String,
+
+    /// The location of the frame's word in the source code
+    pub span: Option<&'r Span>,
+}
+
+/// Determines how execution continues after a pause
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Resume {
+    /// Run until the next breakpoint
+    Continue,
+
+    /// Pause before the next word, wherever it is
+    StepInto,
+
+    /// Pause before the next word that isn't called by the current one
+    StepOver,
+
+    /// Pause before the next word after the current function has returned
+    StepOut,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Breakpoint {
+    /// Pause before any word within the span
+    Span(Span),
+
+    /// Pause before any word with this name
+    Function(String),
+}
+
+impl Breakpoint {
+    /// Create a breakpoint that covers a whole line
+    ///
+    /// The line number starts at 0, as with [`Position`].
+    pub fn line(stream_name: impl Into<String>, line: usize) -> Self {
+        Self::Span(Span {
+            stream_name: stream_name.into(),
+            start: Position {
+                line,
+                column: 0,
+                index: 0,
+            },
+            end: Position {
+                line,
+                column: usize::MAX,
+                index: usize::MAX,
+            },
+        })
+    }
+
+    pub fn matches(&self, word: &str, span: Option<&Span>) -> bool {
+        match self {
+            Breakpoint::Span(breakpoint) => match span {
+                Some(span) => {
+                    span.stream_name == breakpoint.stream_name
+                        && span.start >= breakpoint.start
+                        && span.start <= breakpoint.end
+                }
+                None => false,
+            },
+            Breakpoint::Function(name) => name == word,
+        }
+    }
+}
+
+/// A set of breakpoints
+///
+/// Can be used by implementations of [`Debugger`] to keep track of their
+/// breakpoints.
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Remove all breakpoints that refer to the given stream
+    pub fn clear_stream(&mut self, stream_name: &str) {
+        self.breakpoints.retain(|breakpoint| match breakpoint {
+            Breakpoint::Span(span) => span.stream_name != stream_name,
+            Breakpoint::Function(_) => true,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> + '_ {
+        self.breakpoints.iter()
+    }
+
+    pub fn matches(&self, word: &str, span: Option<&Span>) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|breakpoint| breakpoint.matches(word, span))
+    }
+}
@@ -0,0 +1,206 @@
+use std::io::{self, BufRead, Write};
+
+use termion::{color, style};
+
+use crate::source::Span;
+
+use super::{Breakpoint, Breakpoints, Debugger, Pause, Resume};
+
+/// A debugger that is controlled by commands entered on a console
+///
+/// Pauses before the first word of the program. Enter `help` at the prompt for
+/// a list of commands.
+pub struct Console<R, W> {
+    input: R,
+    output: W,
+    breakpoints: Breakpoints,
+}
+
+impl<R, W> Console<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            breakpoints: Breakpoints::new(),
+        }
+    }
+}
+
+impl<R, W> Console<R, W>
+where
+    R: BufRead,
+    W: Write,
+{
+    fn prompt(&mut self, pause: &Pause) -> io::Result<Resume> {
+        self.print_location(pause)?;
+
+        loop {
+            write!(self.output, "{}(debug){} ", style::Bold, style::Reset)?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                // The input has been closed, so we won't be able to pause
+                // again. Just let the program finish.
+                self.breakpoints.clear();
+                return Ok(Resume::Continue);
+            }
+
+            let mut args = line.split_whitespace();
+            let command = match args.next() {
+                Some(command) => command,
+                None => continue,
+            };
+
+            match command {
+                "s" | "step" => return Ok(Resume::StepInto),
+                "n" | "next" => return Ok(Resume::StepOver),
+                "o" | "out" => return Ok(Resume::StepOut),
+                "c" | "continue" => return Ok(Resume::Continue),
+
+                "b" | "break" => match args.next() {
+                    Some(arg) => self.add_breakpoint(pause, arg)?,
+                    None => {
+                        writeln!(self.output, "Usage: break <line|function>")?
+                    }
+                },
+                "d" | "delete" => {
+                    self.breakpoints.clear();
+                    writeln!(self.output, "Deleted all breakpoints")?;
+                }
+                "l" | "where" => self.print_location(pause)?,
+                "st" | "stack" => {
+                    writeln!(self.output, "{}", pause.stack)?;
+                }
+                "bt" | "frames" => self.print_frames(pause)?,
+                "h" | "help" => self.print_help()?,
+
+                command => {
+                    writeln!(
+                        self.output,
+                        "Unknown command: `{}`. Enter `help` for a list of \
+                        commands.",
+                        command,
+                    )?;
+                }
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, pause: &Pause, arg: &str) -> io::Result<()> {
+        let breakpoint = match arg.parse::<usize>() {
+            Ok(line) => {
+                let stream_name = match pause.span {
+                    Some(span) => span.stream_name.clone(),
+                    None => {
+                        writeln!(self.output, "Current stream is not known")?;
+                        return Ok(());
+                    }
+                };
+
+                // Line numbers are entered as displayed, starting at 1.
+                Breakpoint::line(stream_name, line.saturating_sub(1))
+            }
+            Err(_) => Breakpoint::Function(arg.into()),
+        };
+
+        writeln!(self.output, "Added breakpoint: {:?}", breakpoint)?;
+        self.breakpoints.add(breakpoint);
+
+        Ok(())
+    }
+
+    fn print_location(&mut self, pause: &Pause) -> io::Result<()> {
+        write!(
+            self.output,
+            "{}Paused before{} {}{}{}",
+            color::Fg(color::Cyan),
+            color::Fg(color::Reset),
+            style::Bold,
+            pause.word,
+            style::Reset,
+        )?;
+
+        if let Some(span) = pause.span {
+            write!(self.output, " at ")?;
+            print_span(&mut self.output, span)?;
+        }
+        writeln!(self.output)?;
+
+        if let (Some(span), Some(line)) = (pause.span, pause.source_line()) {
+            writeln!(self.output, "{:5} | {}", span.start.line + 1, line)?;
+        }
+
+        Ok(())
+    }
+
+    fn print_frames(&mut self, pause: &Pause) -> io::Result<()> {
+        write!(self.output, "  -> {}", pause.word)?;
+        if let Some(span) = pause.span {
+            write!(self.output, " at ")?;
+            print_span(&mut self.output, span)?;
+        }
+        writeln!(self.output)?;
+
+        for (i, frame) in pause.frames.iter().rev().enumerate() {
+            write!(self.output, "{:5}: `{}`", i, frame.scope)?;
+            if let Some(span) = frame.span {
+                write!(self.output, " at ")?;
+                print_span(&mut self.output, span)?;
+            }
+            writeln!(self.output)?;
+        }
+
+        Ok(())
+    }
+
+    fn print_help(&mut self) -> io::Result<()> {
+        writeln!(
+            self.output,
+            "\
+s, step           Step into the next word
+n, next           Step over the next word
+o, out            Step out of the current function
+c, continue       Continue until the next breakpoint
+b, break <line>   Add a breakpoint to a line of the current stream
+b, break <name>   Add a breakpoint to a function
+d, delete         Delete all breakpoints
+l, where          Show the current location
+st, stack         Show the values on the stack
+bt, frames        Show the call stack
+h, help           Show this help"
+        )
+    }
+}
+
+impl<R, W> Debugger for Console<R, W>
+where
+    R: BufRead,
+    W: Write,
+{
+    fn is_breakpoint(&mut self, word: &str, span: Option<&Span>) -> bool {
+        self.breakpoints.matches(word, span)
+    }
+
+    fn stop_on_entry(&self) -> bool {
+        true
+    }
+
+    fn pause(&mut self, pause: Pause) -> Resume {
+        // If we can't communicate with the user, there's not much we can do.
+        // Let's just continue.
+        self.prompt(&pause).unwrap_or(Resume::Continue)
+    }
+}
+
+fn print_span(output: &mut dyn Write, span: &Span) -> io::Result<()> {
+    write!(
+        output,
+        "{}{}:{}:{}{}",
+        color::Fg(color::LightBlue),
+        span.stream_name,
+        span.start.line + 1,
+        span.start.column + 1,
+        color::Fg(color::Reset),
+    )
+}
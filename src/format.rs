@@ -0,0 +1,373 @@
+//! Formatting of values according to a template
+//!
+//! Templates contain placeholders, like `"{} items in {:>8}"`, that are
+//! replaced with values. They are used by the `format` builtin, and by
+//! interpolated string literals.
+
+use std::{fmt, iter::Peekable, ops::Range, str::Chars};
+
+use crate::{
+    source::Span,
+    value::{self, Kind},
+};
+
+/// A parsed template
+#[derive(Debug)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, Error> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+
+        let mut chars = template.chars().enumerate().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, c)| c == '{').is_some() => {
+                    literal.push('{');
+                }
+                '}' if chars.next_if(|&(_, c)| c == '}').is_some() => {
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    let end = loop {
+                        match chars.next() {
+                            Some((end, '}')) => break end,
+                            Some((_, c)) => spec.push(c),
+                            None => {
+                                return Err(Error {
+                                    kind: ErrorKind::Unclosed,
+                                    range: i..template.chars().count(),
+                                });
+                            }
+                        }
+                    };
+
+                    let range = i..end + 1;
+                    let spec = Spec::parse(&spec).ok_or_else(|| Error {
+                        kind: ErrorKind::InvalidSpec(spec),
+                        range: range.clone(),
+                    })?;
+
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(literal.split_off(0)));
+                    }
+                    pieces
+                        .push(Piece::Placeholder(Placeholder { range, spec }));
+                }
+                '}' => {
+                    return Err(Error {
+                        kind: ErrorKind::Unmatched,
+                        range: i..i + 1,
+                    });
+                }
+                c => {
+                    literal.push(c);
+                }
+            }
+        }
+
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Ok(Self { pieces })
+    }
+
+    pub fn placeholders(&self) -> impl Iterator<Item = &Placeholder> + '_ {
+        self.pieces.iter().filter_map(|piece| match piece {
+            Piece::Placeholder(placeholder) => Some(placeholder),
+            Piece::Literal(_) => None,
+        })
+    }
+
+    /// Check that there is a value for each placeholder
+    ///
+    /// Values are taken from the top of the stack, for the last placeholder
+    /// first. If there aren't enough, the error points to the first
+    /// placeholder that finds no value.
+    pub fn check_values(&self, available: usize) -> Result<(), Error> {
+        let placeholders: Vec<_> = self.placeholders().collect();
+
+        match placeholders.len().checked_sub(available + 1) {
+            Some(missing) => Err(Error {
+                kind: ErrorKind::MissingValue,
+                range: placeholders[missing].range.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Replace the placeholders with the values, in order
+    ///
+    /// Expects one value per placeholder.
+    pub fn render(&self, values: &[value::Any]) -> String {
+        let mut values = values.iter();
+        let mut output = String::new();
+
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(literal) => output.push_str(literal),
+                Piece::Placeholder(placeholder) => {
+                    let value = values
+                        .next()
+                        .expect("Expected one value per placeholder");
+                    placeholder.spec.format(value, &mut output);
+                }
+            }
+        }
+
+        output
+    }
+}
+
+#[derive(Debug)]
+enum Piece {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug)]
+pub struct Placeholder {
+    /// The characters of the template that make up the placeholder
+    pub range: Range<usize>,
+
+    pub spec: Spec,
+}
+
+/// How a value is formatted, as in `{:<8.2?}`
+///
+/// All parts are optional. The alignment is one of `<`, `^`, or `>`. Numbers
+/// are aligned to the right by default, all other values to the left. The
+/// precision is the number of decimal places of a float, or the maximum
+/// number of characters of a string. `?` formats values the way they would be
+/// written in source code.
+#[derive(Debug, Default)]
+pub struct Spec {
+    pub align: Option<Align>,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+    pub debug: bool,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Option<Self> {
+        let mut result = Self::default();
+        if spec.is_empty() {
+            return Some(result);
+        }
+
+        let mut chars = spec.strip_prefix(':')?.chars().peekable();
+
+        result.align = match chars.peek() {
+            Some('<') => Some(Align::Left),
+            Some('^') => Some(Align::Center),
+            Some('>') => Some(Align::Right),
+            _ => None,
+        };
+        if result.align.is_some() {
+            chars.next();
+        }
+
+        result.width = parse_number(&mut chars);
+        if chars.next_if_eq(&'.').is_some() {
+            result.precision = Some(parse_number(&mut chars)?);
+        }
+        result.debug = chars.next_if_eq(&'?').is_some();
+
+        if chars.next().is_some() {
+            return None;
+        }
+
+        Some(result)
+    }
+
+    fn format(&self, value: &value::Any, output: &mut String) {
+        let formatted = match (&value.kind, self.precision) {
+            (Kind::Float(value), Some(precision)) => {
+                format!("{:.*}", precision, value.into_inner())
+            }
+            (Kind::String(value), Some(precision)) if !self.debug => {
+                value.chars().take(precision).collect()
+            }
+            (kind, _) if self.debug => {
+                kind.repr().unwrap_or_else(|| kind.to_string())
+            }
+            (kind, _) => kind.to_string(),
+        };
+
+        let align = self.align.unwrap_or(match value.kind {
+            Kind::Float(_) | Kind::Number(_) => Align::Right,
+            _ => Align::Left,
+        });
+        let padding = self
+            .width
+            .unwrap_or(0)
+            .saturating_sub(formatted.chars().count());
+        let (before, after) = match align {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+
+        output.extend(std::iter::repeat_n(' ', before));
+        output.push_str(&formatted);
+        output.extend(std::iter::repeat_n(' ', after));
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits.parse().ok()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Find the span of some characters within a string literal
+///
+/// Returns `None`, if the span doesn't belong to a string literal that
+/// contains exactly the given string. That is the case for strings that were
+/// computed, instead of written in the source code.
+pub fn span_within(
+    literal: &Span,
+    string: &str,
+    range: Range<usize>,
+) -> Option<Span> {
+    // Within a single line, a newline can only come from an escape code.
+    // Otherwise, we have to assume that newlines are literal.
+    let single_line = literal.start.line == literal.end.line;
+
+    let mut position = literal.start;
+    position.column += 1;
+    position.index += 1;
+
+    let mut positions = Vec::new();
+    for c in string.chars() {
+        positions.push(position);
+
+        let is_escaped = match c {
+            '\n' => single_line,
+            '"' | '\\' => true,
+            _ => false,
+        };
+
+        if is_escaped {
+            position.column += 2;
+            position.index += 2;
+        } else if c == '\n' {
+            position.line += 1;
+            position.column = 0;
+            position.index += 1;
+        } else {
+            position.column += 1;
+            position.index += c.len_utf8();
+        }
+    }
+
+    // The closing quote.
+    if position.index != literal.end.index || range.is_empty() {
+        return None;
+    }
+
+    Some(Span {
+        stream_name: literal.stream_name.clone(),
+        start: *positions.get(range.start)?,
+        end: *positions.get(range.end - 1)?,
+    })
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+
+    /// The characters of the template that the error refers to
+    pub range: Range<usize>,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Unclosed,
+    Unmatched,
+    InvalidSpec(String),
+    MissingValue,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ErrorKind::Unclosed => write!(f, "Placeholder is not closed"),
+            ErrorKind::Unmatched => {
+                write!(f, "Unmatched `}}` (write `}}}}` for a literal `}}`)")
+            }
+            ErrorKind::InvalidSpec(spec) => {
+                write!(f, "Invalid placeholder: `{{{}}}`", spec)
+            }
+            ErrorKind::MissingValue => {
+                write!(f, "No value on the stack for placeholder")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::value::{self, Kind};
+
+    use super::Template;
+
+    fn render(template: &str, values: Vec<Kind>) -> String {
+        let values: Vec<_> = values
+            .into_iter()
+            .map(|kind| value::Any { kind, span: None })
+            .collect();
+        Template::parse(template).unwrap().render(&values)
+    }
+
+    #[test]
+    fn templates_should_support_width_precision_and_debug() {
+        let float = Kind::Float(1.2345.into());
+        let string = || Kind::String("ab\nc".into());
+
+        assert_eq!(render("{} {{x}}", vec![Kind::Number(3)]), "3 {x}");
+        assert_eq!(render("[{:4}]", vec![Kind::Number(3)]), "[   3]");
+        assert_eq!(render("[{:<4}]", vec![Kind::Number(3)]), "[3   ]");
+        assert_eq!(render("[{:^5}]", vec![Kind::Number(3)]), "[  3  ]");
+        assert_eq!(render("{:.2}", vec![float]), "1.23");
+        assert_eq!(render("{:.2}", vec![string()]), "ab");
+        assert_eq!(render("{:?}", vec![string()]), "\"ab\\nc\"");
+    }
+
+    #[test]
+    fn templates_should_report_the_location_of_errors() {
+        let error = |template| Template::parse(template).unwrap_err().range;
+
+        assert_eq!(error("a {} {:x}"), 5..9);
+        assert_eq!(error("a } b"), 2..3);
+        assert_eq!(error("a {"), 2..3);
+    }
+
+    #[test]
+    fn templates_should_point_to_the_placeholder_without_a_value() {
+        let template = Template::parse("{} {} {} {}").unwrap();
+        let missing = |available| {
+            template
+                .check_values(available)
+                .map_err(|error| error.range)
+        };
+
+        assert_eq!(missing(4), Ok(()));
+        assert_eq!(missing(2), Err(3..5));
+        assert_eq!(missing(0), Err(9..11));
+    }
+}
@@ -1,21 +1,32 @@
-use std::{collections::HashMap, fmt};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    rc::Rc,
+};
 
 use crate::{
     context::{self, Context},
+    prelude::*,
     stack::Stack,
     value::{
+        t,
         types::{Type, Typed},
         v,
     },
 };
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Functions<T> {
-    scopes: HashMap<Scope, HashMap<String, Node<T>>>,
+    scopes: HashMap<Scope, HashMap<String, Node>>,
+    functions: Vec<T>,
+    signatures: Vec<Vec<&'static dyn Type>>,
     root: Scope,
     parents: HashMap<Scope, Scope>,
     names: HashMap<Scope, String>,
     next_scope_id: u64,
+    generation: u64,
+    modified: HashMap<Scope, u64>,
 }
 
 impl<T> Functions<T>
@@ -33,10 +44,14 @@ where
 
         Self {
             scopes,
+            functions: Vec::new(),
+            signatures: Vec::new(),
             root,
             parents: HashMap::new(),
             names,
             next_scope_id: 1,
+            generation: 0,
+            modified: HashMap::new(),
         }
     }
 
@@ -51,6 +66,9 @@ where
         S: Into<String>,
     {
         let name = name.into();
+        let slot = Slot {
+            index: self.functions.len(),
+        };
 
         let functions = self.scopes.get_mut(&scope).expect("Scope not found");
 
@@ -66,19 +84,23 @@ where
                 });
             }
 
-            functions.insert(name, Node::Function(f));
-            return Ok(self);
-        }
+            functions.insert(name, Node::Function(slot));
+        } else {
+            let node = functions
+                .entry(name.clone())
+                .or_insert(Node::Type(HashMap::new()));
 
-        let node = functions
-            .entry(name.clone())
-            .or_insert(Node::Type(HashMap::new()));
+            node.insert(args, slot).map_err(|conflicting| DefineError {
+                name,
+                conflicting,
+                scope_id: scope.id,
+            })?;
+        }
 
-        node.insert(args, f).map_err(|conflicting| DefineError {
-            name,
-            conflicting,
-            scope_id: scope.id,
-        })?;
+        self.functions.push(f);
+        self.signatures.push(args.to_vec());
+        self.generation += 1;
+        self.modified.insert(scope, self.generation);
 
         Ok(self)
     }
@@ -89,57 +111,208 @@ where
         name: &str,
         stack: &Stack,
     ) -> Result<T, GetError> {
+        self.resolve(scope, name, stack)
+            .map(|slot| self.function(slot).clone())
+    }
+
+    /// Find the slot of the function that matches the name and stack
+    ///
+    /// Like [`Functions::get`], but returns the slot the function is stored
+    /// in, which can be used to access it later without another lookup.
+    pub fn resolve(
+        &self,
+        scope: Scope,
+        name: &str,
+        stack: &Stack,
+    ) -> Result<Slot, GetError> {
+        self.resolve_shape(scope, name, stack).map(|(slot, _)| slot)
+    }
+
+    /// Like `resolve`, but also returns which part of the stack was looked at
+    fn resolve_shape(
+        &self,
+        scope: Scope,
+        name: &str,
+        stack: &Stack,
+    ) -> Result<(Slot, Shape), GetError> {
         let mut scope = scope;
+        let mut shape = Shape::default();
 
         loop {
-            match self.get_inner(scope, name, stack) {
-                Ok(function) => return Ok(function),
+            if let Some(slot) = self.get_inner(scope, name, stack, &mut shape) {
+                return Ok((slot, shape));
+            }
+
+            match self.parents.get(&scope) {
+                Some(parent) => scope = *parent,
+                None => {
+                    let functions =
+                        self.scopes.get(&scope).expect("Scope not found");
 
-                Err(error) => match self.parents.get(&scope) {
-                    Some(parent) => scope = *parent,
-                    None => return Err(error),
-                },
+                    return Err(GetError {
+                        candidates: self.candidates_for(functions, name),
+                        scope: self.scope_name(scope),
+                    });
+                }
             }
         }
     }
 
+    /// Find the slot of a function without looking at the stack
+    ///
+    /// This only succeeds, if the function that a call to `resolve` would
+    /// return doesn't depend on the types on the stack. Returns `None`, if
+    /// that's not the case, or if no function of that name has been defined.
+    ///
+    /// The result is only valid until the next function is defined, as that
+    /// function could shadow the one that was returned. Use
+    /// [`Functions::defined_since`] to detect that.
+    pub fn resolve_static(&self, scope: Scope, name: &str) -> Option<Slot> {
+        let mut scope = scope;
+
+        loop {
+            let functions = self.scopes.get(&scope).expect("Scope not found");
+
+            match functions.get(name) {
+                Some(Node::Function(slot)) => return Some(*slot),
+                Some(Node::Type(_)) => return None,
+                None => scope = *self.parents.get(&scope)?,
+            }
+        }
+    }
+
+    pub fn function(&self, slot: Slot) -> &T {
+        // Shouldn't panic. Slots are only handed out for functions that exist,
+        // and functions are never removed.
+        &self.functions[slot.index]
+    }
+
+    /// Returns the types that the function in the slot was defined with
+    pub fn signature(&self, slot: Slot) -> &[&'static dyn Type] {
+        &self.signatures[slot.index]
+    }
+
+    /// Counts the function definitions so far
+    ///
+    /// Can be used to detect whether any functions have been defined since a
+    /// previous call.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether functions were defined in the scope or its parents since the
+    /// given generation
+    pub fn defined_since(&self, scope: Scope, generation: u64) -> bool {
+        if generation == self.generation {
+            return false;
+        }
+
+        let mut scope = Some(scope);
+
+        while let Some(current) = scope {
+            if let Some(&modified) = self.modified.get(&current) {
+                if modified > generation {
+                    return true;
+                }
+            }
+
+            scope = self.parents.get(&current).copied();
+        }
+
+        false
+    }
+
+    /// Whether a function of that name is defined in the scope or its parents
+    pub fn is_defined(&self, scope: Scope, name: &str) -> bool {
+        self.scope_chain(scope)
+            .any(|scope| self.scopes[&scope].contains_key(name))
+    }
+
+    /// The signatures of all functions of that name that are visible from
+    /// the scope
+    ///
+    /// Functions that are defined closer to the scope come first.
+    pub fn signatures(&self, scope: Scope, name: &str) -> Signatures {
+        let mut signatures = Vec::new();
+
+        for scope in self.scope_chain(scope) {
+            let mut candidates =
+                self.candidates_for(&self.scopes[&scope], name);
+            candidates.sort_by_key(|signature| {
+                signature.iter().map(|t| t.name()).collect::<Vec<_>>()
+            });
+            signatures.extend(candidates);
+        }
+
+        signatures
+    }
+
+    /// The names of the functions that are defined in the scope itself
+    pub fn names(&self, scope: Scope) -> Vec<&str> {
+        let mut names: Vec<_> = self
+            .scopes
+            .get(&scope)
+            .expect("Scope not found")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        names.sort_unstable();
+
+        names
+    }
+
+    /// The scope, followed by all of its parents
+    fn scope_chain(&self, scope: Scope) -> impl Iterator<Item = Scope> + '_ {
+        std::iter::successors(Some(scope), |scope| {
+            self.parents.get(scope).copied()
+        })
+    }
+
     fn get_inner(
         &self,
         scope: Scope,
         name: &str,
         stack: &Stack,
-    ) -> Result<T, GetError> {
+        shape: &mut Shape,
+    ) -> Option<Slot> {
         let functions = self.scopes.get(&scope).expect("Scope not found");
 
-        let mut node = functions.get(name).ok_or_else(|| GetError {
-            candidates: self.candidates_for(functions, name),
-            scope: self.scope_name(scope),
-        })?;
+        let mut node = functions.get(name)?;
+        let mut values = stack.peek();
+        let mut depth = 0;
 
-        for expr in stack.peek() {
+        loop {
             let map = match node {
                 Node::Type(map) => map,
-                Node::Function(f) => return Ok(f.clone()),
+                Node::Function(slot) => {
+                    shape.depth = shape.depth.max(depth);
+                    return Some(*slot);
+                }
             };
 
-            node = map.get(expr.get_type()).ok_or_else(|| GetError {
-                candidates: self.candidates_for(functions, name),
-                scope: self.scope_name(scope),
-            })?;
-        }
+            let value = match values.next() {
+                Some(value) => value,
+                None => {
+                    shape.depth = shape.depth.max(depth);
+                    shape.exhausted = true;
+                    return None;
+                }
+            };
+            depth += 1;
 
-        match node {
-            Node::Type(_) => Err(GetError {
-                candidates: self.candidates_for(functions, name),
-                scope: self.scope_name(scope),
-            }),
-            Node::Function(f) => Ok(f.clone()),
+            node = match map.get(value.get_type()) {
+                Some(node) => node,
+                None => {
+                    shape.depth = shape.depth.max(depth);
+                    return None;
+                }
+            };
         }
     }
 
     fn candidates_for(
         &self,
-        functions: &HashMap<String, Node<T>>,
+        functions: &HashMap<String, Node>,
         name: &str,
     ) -> Signatures {
         let mut candidates = Vec::new();
@@ -160,7 +333,7 @@ where
         parent: Scope,
         name: impl Into<String>,
     ) -> Scope {
-        assert!(self.next_scope_id < u64::max_value());
+        assert!(self.next_scope_id < u64::MAX);
 
         let id = self.next_scope_id;
         self.next_scope_id += 1;
@@ -173,7 +346,7 @@ where
         scope
     }
 
-    fn scope_name(&self, scope: Scope) -> String {
+    pub fn scope_name(&self, scope: Scope) -> String {
         let mut scope = scope;
 
         let mut name = self
@@ -214,17 +387,120 @@ pub struct Scope {
     id: u64,
 }
 
-#[derive(Debug)]
-enum Node<T> {
-    Type(HashMap<&'static dyn Type, Node<T>>),
-    Function(T),
+/// Identifies where a defined function is stored
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Slot {
+    index: usize,
+}
+
+/// Remembers the function that a word was last resolved to
+///
+/// Meant to be kept at a call site, where the same word is resolved again and
+/// again, usually against the same types. As long as the scope and the types
+/// on top of the stack stay the same, and no functions are defined that could
+/// change the result, the cached slot is returned without another lookup.
+#[derive(Clone, Debug, Default)]
+pub struct InlineCache {
+    entry: RefCell<Option<CacheEntry>>,
+    hits: Cell<u64>,
+}
+
+impl InlineCache {
+    pub fn resolve<T>(
+        &self,
+        functions: &Functions<T>,
+        scope: Scope,
+        name: &str,
+        stack: &Stack,
+    ) -> Result<Slot, GetError>
+    where
+        T: Clone,
+    {
+        if let Some(entry) = self.entry.borrow_mut().as_mut() {
+            if entry.scope == scope
+                && !functions.defined_since(scope, entry.generation)
+                && entry.matches(stack)
+            {
+                entry.generation = functions.generation();
+                self.hits.set(self.hits.get() + 1);
+                return Ok(entry.slot);
+            }
+        }
+
+        let (slot, shape) = functions.resolve_shape(scope, name, stack)?;
+
+        *self.entry.borrow_mut() = Some(CacheEntry {
+            scope,
+            types: stack
+                .peek()
+                .take(shape.depth)
+                .map(|value| value.get_type().name())
+                .collect(),
+            exhausted: shape.exhausted,
+            slot,
+            generation: functions.generation(),
+        });
+
+        Ok(slot)
+    }
+
+    /// How often the cached slot was returned, without another lookup
+    pub fn hits(&self) -> u64 {
+        self.hits.get()
+    }
 }
 
-impl<T> Node<T> {
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    scope: Scope,
+    types: Vec<&'static str>,
+    exhausted: bool,
+    slot: Slot,
+    generation: u64,
+}
+
+impl CacheEntry {
+    fn matches(&self, stack: &Stack) -> bool {
+        // If the lookup ran out of values, adding values to the stack could
+        // change its result.
+        if self.exhausted && stack.len() != self.types.len() {
+            return false;
+        }
+
+        let mut values = stack.peek();
+
+        for &name in &self.types {
+            match values.next() {
+                Some(value) if value.get_type().name() == name => (),
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// The part of the stack that a lookup depended on
+#[derive(Debug, Default)]
+struct Shape {
+    /// The number of values from the top of the stack that were looked at
+    depth: usize,
+
+    /// Whether the lookup ran out of values to look at
+    exhausted: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Type(HashMap<&'static dyn Type, Node>),
+    Function(Slot),
+}
+
+impl Node {
     fn insert(
         &mut self,
         args: &[&'static dyn Type],
-        f: T,
+        slot: Slot,
     ) -> Result<(), Signatures> {
         let map = match self {
             Node::Type(map) => map,
@@ -255,7 +531,7 @@ impl<T> Node<T> {
         };
 
         if let Some(node) = map.get_mut(t) {
-            return node.insert(args, f).map_err(|mut conflicting| {
+            return node.insert(args, slot).map_err(|mut conflicting| {
                 for signature in &mut conflicting {
                     signature.insert(0, t);
                 }
@@ -263,7 +539,7 @@ impl<T> Node<T> {
             });
         }
 
-        let mut node = Node::Function(f);
+        let mut node = Node::Function(slot);
 
         for &t in args {
             let mut map = HashMap::new();
@@ -329,16 +605,20 @@ pub type Signatures = Vec<Vec<&'static dyn Type>>;
 
 pub enum Function<H> {
     Builtin(Builtin<H>),
-    UserDefined { body: v::List },
+    UserDefined {
+        body: v::List,
+        effect: Option<Rc<Effect>>,
+    },
 }
 
 impl<H> Clone for Function<H> {
     fn clone(&self) -> Self {
         match self {
             Function::Builtin(f) => Function::Builtin(*f),
-            Function::UserDefined { body } => {
-                Function::UserDefined { body: body.clone() }
-            }
+            Function::UserDefined { body, effect } => Function::UserDefined {
+                body: body.clone(),
+                effect: effect.clone(),
+            },
         }
     }
 }
@@ -347,8 +627,69 @@ impl<H> fmt::Debug for Function<H> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Function::Builtin(_) => write!(f, "builtin"),
-            Function::UserDefined { body } => write!(f, "{:?}", body),
+            Function::UserDefined { body, .. } => write!(f, "{:?}", body),
+        }
+    }
+}
+
+/// The declared stack effect of a function, like `( number number -- number )`
+///
+/// The inputs are the types the function takes from the stack, the outputs
+/// the types it leaves there. The last type of each describes the top of the
+/// stack.
+#[derive(Clone, Debug)]
+pub struct Effect {
+    pub inputs: Vec<&'static dyn Type>,
+    pub outputs: Vec<&'static dyn Type>,
+}
+
+impl Effect {
+    /// Parse the list that a stack effect declaration evaluates to
+    pub fn parse(list: &v::List) -> Result<Self, context::Error> {
+        let invalid = |message: String| context::Error::InvalidEffect {
+            message,
+            span: list.span.clone(),
+        };
+
+        let mut inputs = Vec::new();
+        let mut outputs = None;
+
+        for item in &list.inner.items {
+            let word = item.clone().cast(t::Word)?;
+
+            if word.inner == "--" {
+                if outputs.is_some() {
+                    return Err(invalid("More than one `--`".into()));
+                }
+                outputs = Some(Vec::new());
+                continue;
+            }
+
+            let ty = t::from_name(&word.inner).ok_or_else(|| {
+                invalid(format!("Unknown type `{}`", word.inner))
+            })?;
+            outputs.as_mut().unwrap_or(&mut inputs).push(ty);
+        }
+
+        let outputs = outputs.ok_or_else(|| {
+            invalid("Expected `--` between inputs and outputs".into())
+        })?;
+
+        Ok(Self { inputs, outputs })
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for ty in &self.inputs {
+            write!(f, " {}", ty.name())?;
+        }
+        write!(f, " --")?;
+        for ty in &self.outputs {
+            write!(f, " {}", ty.name())?;
         }
+        write!(f, " )")
     }
 }
 
@@ -363,7 +704,7 @@ mod tests {
         value::{t, types::Type, v},
     };
 
-    use super::{DefineError, Functions};
+    use super::{DefineError, Functions, InlineCache};
 
     type Result = std::result::Result<(), DefineError>;
 
@@ -560,4 +901,91 @@ mod tests {
         assert!(result.is_err());
         Ok(())
     }
+
+    #[test]
+    fn inline_cache_should_be_invalidated_by_shadowing_definition() -> Result {
+        let mut functions = Functions::new();
+        let stack = Stack::new();
+        let cache = InlineCache::default();
+
+        let parent_scope = functions.root_scope();
+        let child_scope = functions.new_scope(parent_scope, "child");
+
+        functions.define(parent_scope, "a", &[], 1)?;
+
+        let slot = cache.resolve(&functions, child_scope, "a", &stack);
+        assert_eq!(slot.map(|slot| *functions.function(slot)), Ok(1));
+
+        functions.define(child_scope, "a", &[], 2)?;
+
+        let slot = cache.resolve(&functions, child_scope, "a", &stack);
+        assert_eq!(slot.map(|slot| *functions.function(slot)), Ok(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn inline_cache_should_respect_the_types_on_the_stack() -> Result {
+        let mut functions = Functions::new();
+        let scope = functions.root_scope();
+        let mut stack = Stack::new();
+        let cache_a = InlineCache::default();
+        let cache_b = InlineCache::default();
+
+        functions
+            .define(scope, "a", &[&t::Number], 1)?
+            .define(scope, "a", &[&t::Float], 2)?
+            .define(scope, "b", &[&t::Number, &t::Number], 3)?;
+
+        let get = |cache: &InlineCache, name: &str, stack: &Stack| {
+            cache
+                .resolve(&functions, scope, name, stack)
+                .map(|slot| *functions.function(slot))
+        };
+
+        stack.push(v::Number::new(0, None));
+        assert_eq!(get(&cache_a, "a", &stack), Ok(1));
+        assert!(get(&cache_b, "b", &stack).is_err());
+
+        stack.push(v::Float::new(0.0.into(), None));
+        assert_eq!(get(&cache_a, "a", &stack), Ok(2));
+
+        stack.pop::<v::Any>().unwrap();
+        stack.push(v::Number::new(0, None));
+        assert_eq!(get(&cache_b, "b", &stack), Ok(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_should_list_signatures_from_the_scope_and_its_parents() -> Result {
+        let mut functions = Functions::new();
+        let root = functions.root_scope();
+        let child = functions.new_scope(root, "child");
+
+        functions
+            .define(root, "a", &[&t::Number], 1)?
+            .define(root, "a", &[&t::Float], 2)?
+            .define(child, "a", &[&t::String], 3)?
+            .define(child, "b", &[], 4)?;
+
+        let names = |scope| {
+            functions
+                .signatures(scope, "a")
+                .iter()
+                .map(|signature| {
+                    signature.iter().map(|t| t.name()).collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(child), vec![["string"], ["float"], ["number"]]);
+        assert_eq!(names(root), vec![["float"], ["number"]]);
+
+        assert!(functions.is_defined(child, "b"));
+        assert!(!functions.is_defined(root, "b"));
+        assert_eq!(functions.names(child), vec!["a", "b"]);
+
+        Ok(())
+    }
 }
@@ -1,47 +1,76 @@
 pub mod error;
 
-use std::{borrow::Cow, collections::HashMap, io};
+mod vm;
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io,
+    rc::Rc,
+    sync::{Arc, OnceLock},
+};
 
 use parser::Parser;
 
 use crate::{
     builtins::builtins,
-    call_stack::{CallStack, StackFrame},
+    call_stack::CallStack,
+    check::Checker,
     context::{self, Context},
-    functions::{self, Builtin, DefineError, Function, Functions, Scope},
-    pipeline::{parser, Pipeline},
+    debugger::Debugger,
+    functions::{Builtin, DefineError, Function, Functions, Scope, Slot},
+    observer::{Definition, Observer},
+    pipeline::{compiler::Chunk, parser, Pipeline},
     prelude::*,
-    source::SpanMerge,
+    source::{Expression, Span, SpanMerge},
     stack::Stack,
+    test_case::{TestCase, TestResult},
     value::{self, types::Type, v},
 };
 
-use self::error::Error;
+use self::{
+    error::{Error, ErrorKind},
+    vm::{Debugging, Mode},
+};
 
 pub struct Interpreter<Host> {
-    streams: HashMap<String, Box<dyn io::Read>>,
+    modules: HashMap<String, Arc<Vec<Expression>>>,
     stdout: Box<dyn io::Write>,
     stderr: Box<dyn io::Write>,
 
     sources: HashMap<String, String>,
+    strip_annotations: bool,
 
     functions: Functions<Function<Host>>,
+    chunks: HashMap<Slot, Rc<Chunk>>,
+    list_chunks: HashMap<Span, Rc<Chunk>>,
     stack: Stack,
     call_stack: CallStack,
+    test_cases: Vec<TestCase>,
+
+    debugging: Option<Debugging>,
+    observers: Vec<Box<dyn Observer>>,
 }
 
 impl<Host> Interpreter<Host> {
     pub fn new(stdout: Box<dyn io::Write>, stderr: Box<dyn io::Write>) -> Self {
         Self {
-            streams: HashMap::new(),
+            modules: HashMap::new(),
             stdout,
             stderr,
 
             sources: HashMap::new(),
+            strip_annotations: false,
 
             functions: Functions::new(),
+            chunks: HashMap::new(),
+            list_chunks: HashMap::new(),
             stack: Stack::new(),
             call_stack: CallStack::new(),
+            test_cases: Vec::new(),
+
+            debugging: None,
+            observers: Vec::new(),
         }
     }
 
@@ -50,43 +79,94 @@ impl<Host> Interpreter<Host> {
         self
     }
 
+    /// Leave out all stack annotations, like `[: number :]`, when parsing
+    ///
+    /// This saves the cost of checking them at runtime. Only applies to code
+    /// that is parsed afterwards, so to strip them from the prelude and the
+    /// bundled modules, this needs to be called before loading those.
+    pub fn with_annotations_stripped(mut self) -> Self {
+        self.strip_annotations = true;
+        self
+    }
+
     pub fn with_default_prelude(
         mut self,
         host: &mut Host,
     ) -> Result<Self, Error> {
-        let name = "<prelude>";
-        let mut prelude = &include_bytes!("../kr/src/prelude.kr")[..];
+        static PRELUDE: OnceLock<Arc<Vec<Expression>>> = OnceLock::new();
 
-        let prelude_pipeline = Pipeline::new(name.into(), &mut prelude);
+        let name = "<prelude>";
+        let source = include_str!("../kr/src/prelude.kr");
 
-        let mut source = String::new();
-        self.evaluate_expressions(
-            host,
-            self.functions.root_scope(),
-            prelude_pipeline.parser,
-            &mut source,
-        )?;
+        // Errors in other modules might produce stack traces with spans that
+        // refer to the prelude.
+        self.sources.insert(name.into(), source.into());
 
-        // We panic on errors in the prelude itself, but errors in other modules
-        // might still produce stack traces with spans that refer to the
-        // prelude.
-        self.sources.insert(name.into(), source);
-        self.streams.insert(name.into(), Box::new(prelude));
+        let scope = self.functions.root_scope();
+        for expression in
+            bundled(&PRELUDE, name, source, self.strip_annotations).iter()
+        {
+            self.evaluate_expression(host, scope, expression.clone())?;
+        }
 
         Ok(self)
     }
 
     pub fn with_default_modules(mut self) -> Self {
-        self.sources
-            .insert("std".into(), include_str!("../kr/src/std.kr").into());
-        self.streams.insert(
-            "std".into(),
-            Box::new(&include_bytes!("../kr/src/std.kr")[..]),
-        );
+        static STD: OnceLock<Arc<Vec<Expression>>> = OnceLock::new();
+
+        let name = "std";
+        let source = include_str!("../kr/src/std.kr");
+
+        self.sources.insert(name.into(), source.into());
+        let expressions = bundled(&STD, name, source, self.strip_annotations);
+        self.modules.insert(name.into(), expressions);
 
         self
     }
 
+    /// Create an isolated copy of this interpreter
+    ///
+    /// The copy starts out with everything that has been set up in this
+    /// interpreter, like builtins, prelude, and modules. This is much cheaper
+    /// than setting up a new interpreter from scratch, so if many programs
+    /// need to be run, it makes sense to set up one interpreter as a template,
+    /// then fork a fresh copy from it for each program.
+    ///
+    /// Debuggers and observers are not carried over to the copy.
+    pub fn fork(
+        &self,
+        stdout: Box<dyn io::Write>,
+        stderr: Box<dyn io::Write>,
+    ) -> Self {
+        // Compiled chunks carry caches that are only valid for the functions
+        // they were filled from. The copies must not share them.
+        let chunks = self
+            .chunks
+            .iter()
+            .map(|(&slot, chunk)| (slot, Rc::new(Chunk::clone(chunk))))
+            .collect();
+
+        Self {
+            modules: self.modules.clone(),
+            stdout,
+            stderr,
+
+            sources: self.sources.clone(),
+            strip_annotations: self.strip_annotations,
+
+            functions: self.functions.clone(),
+            chunks,
+            list_chunks: HashMap::new(),
+            stack: self.stack.clone(),
+            call_stack: self.call_stack.clone(),
+            test_cases: self.test_cases.clone(),
+
+            debugging: None,
+            observers: Vec::new(),
+        }
+    }
+
     pub fn with_builtin(
         mut self,
         name: &str,
@@ -102,33 +182,168 @@ impl<Host> Interpreter<Host> {
         Ok(self)
     }
 
+    /// The source code of all streams that have been read so far
+    ///
+    /// Includes the bundled prelude and modules.
+    pub fn sources(&self) -> &HashMap<String, String> {
+        &self.sources
+    }
+
+    /// Attach a debugger that controls the execution of the program
+    pub fn with_debugger(mut self, debugger: Box<dyn Debugger>) -> Self {
+        let mode = if debugger.stop_on_entry() {
+            Mode::StepInto
+        } else {
+            Mode::Continue
+        };
+
+        self.debugging = Some(Debugging { debugger, mode });
+        self
+    }
+
+    /// Attach an observer that is notified of every evaluation step
+    ///
+    /// Any number of observers can be attached. They are notified in the order
+    /// they were attached in.
+    pub fn with_observer(mut self, observer: Box<dyn Observer>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
     pub fn run(
         mut self,
         host: &mut Host,
         name: Cow<str>,
         mut program: Box<dyn io::Read>,
     ) -> Result<Vec<value::Any>, Error> {
-        let pipeline = Pipeline::new(name.clone().into_owned(), &mut program);
+        self.evaluate_program(host, name, &mut program)?;
+        Ok(self.stack.into_vec())
+    }
+
+    /// Run a program, then each of the test cases it registered
+    ///
+    /// Returns an error, if the program itself fails. Otherwise, returns the
+    /// result of each test case. Test cases run one after the other, each
+    /// starting with an empty stack, but with all the definitions made by the
+    /// program.
+    pub fn test(
+        self,
+        host: &mut Host,
+        name: Cow<str>,
+        program: Box<dyn io::Read>,
+    ) -> Result<Vec<TestResult>, Error> {
+        self.test_matching(host, name, program, |_| true)
+    }
+
+    /// Like [`Interpreter::test`], but only runs the test cases whose names
+    /// match the filter
+    pub fn test_matching(
+        mut self,
+        host: &mut Host,
+        name: Cow<str>,
+        mut program: Box<dyn io::Read>,
+        filter: impl Fn(&str) -> bool,
+    ) -> Result<Vec<TestResult>, Error> {
+        self.evaluate_program(host, name, &mut program)?;
+
+        let mut results = Vec::new();
+
+        for test_case in std::mem::take(&mut self.test_cases) {
+            if !filter(&test_case.name) {
+                continue;
+            }
+
+            self.stack = Stack::new();
+            self.call_stack = CallStack::new();
+
+            let result =
+                self.evaluate_list(host, test_case.body).map_err(|error| {
+                    Error {
+                        kind: Box::new(error.into()),
+                        call_stack: self.call_stack.clone(),
+                    }
+                });
+
+            if let Err(error) = &result {
+                self.print_error(error);
+            }
+
+            results.push(TestResult {
+                name: test_case.name,
+                span: test_case.span,
+                result,
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// Check a program for errors, without running it
+    ///
+    /// Only finds errors that would definitely happen, if the program ran, like
+    /// words that don't refer to any function. The program can use everything
+    /// that has been set up in this interpreter, like builtins, prelude, and
+    /// modules. Prints all errors that were found, and returns them.
+    pub fn check(
+        &mut self,
+        name: Cow<str>,
+        program: &mut dyn io::Read,
+    ) -> Vec<Error> {
+        let name = name.into_owned();
+        let mut pipeline = Pipeline::new(name.clone(), program);
+        let source = self.sources.entry(name).or_default();
+
+        let errors = match pipeline.parser.expressions(source) {
+            Ok(expressions) => Checker::new(&self.functions, &self.modules)
+                .check(&expressions)
+                .into_iter()
+                .map(|error| Error {
+                    kind: Box::new(ErrorKind::Check(error)),
+                    call_stack: CallStack::new(),
+                })
+                .collect(),
+            Err(error) => vec![Error {
+                kind: Box::new(error.into()),
+                call_stack: CallStack::new(),
+            }],
+        };
+
+        for error in &errors {
+            self.print_error(error);
+        }
+
+        errors
+    }
+
+    fn evaluate_program(
+        &mut self,
+        host: &mut Host,
+        name: Cow<str>,
+        program: &mut dyn io::Read,
+    ) -> Result<(), Error> {
+        let name = name.into_owned();
+        let mut pipeline = Pipeline::new(name.clone(), program);
+        if self.strip_annotations {
+            pipeline.parser.strip_annotations();
+        }
 
-        let mut source = String::new();
         let result = self.evaluate_expressions(
             host,
             self.functions.root_scope(),
             pipeline.parser,
-            &mut source,
+            name,
         );
-        if let Err(error) = result {
-            self.sources.insert(name.clone().into_owned(), source);
-            self.streams.insert(name.into_owned(), program);
+        if let Err(error) = &result {
+            self.print_error(error);
+        }
 
-            if let Err(error) = error.print(&self.sources, &mut self.stderr) {
-                println!("Error printing error: {}", error)
-            }
+        result
+    }
 
-            return Err(error);
+    fn print_error(&mut self, error: &Error) {
+        if let Err(error) = error.print(&self.sources, &mut self.stderr) {
+            println!("Error printing error: {}", error)
         }
-
-        Ok(self.stack.into_vec())
     }
 
     fn evaluate_expressions<R>(
@@ -136,12 +351,16 @@ impl<Host> Interpreter<Host> {
         host: &mut Host,
         scope: Scope,
         mut parser: Parser<R>,
-        source: &mut String,
+        name: String,
     ) -> Result<(), Error>
     where
         R: io::Read,
     {
         loop {
+            // The source is kept with the others while we're evaluating, so
+            // it's available to the debugger and for error messages.
+            let source = self.sources.entry(name.clone()).or_default();
+
             let expression = match parser.next_expression(source) {
                 Ok(expression) => expression,
                 Err(parser::Error::EndOfStream) => {
@@ -149,27 +368,67 @@ impl<Host> Interpreter<Host> {
                 }
                 Err(error) => {
                     return Err(Error {
-                        kind: error.into(),
+                        kind: Box::new(error.into()),
                         call_stack: self.call_stack.clone(),
                     });
                 }
             };
 
-            let list_scope = self.functions.new_scope(scope, "list");
-
-            let result = self.evaluate_value(
-                host,
-                scope,
-                value::Any::from_expression(expression, list_scope),
-            );
-            if let Err(error) = result {
-                return Err(Error {
-                    kind: error.into(),
-                    call_stack: self.call_stack.clone(),
-                });
-            }
+            self.evaluate_expression(host, scope, expression)?;
         }
     }
+
+    fn evaluate_expression(
+        &mut self,
+        host: &mut Host,
+        scope: Scope,
+        expression: Expression,
+    ) -> Result<(), Error> {
+        let list_scope = self.functions.new_scope(scope, "list");
+
+        self.evaluate_value(
+            host,
+            scope,
+            value::Any::from_expression(expression, list_scope),
+        )
+        .map_err(|error| Error {
+            kind: Box::new(error.into()),
+            call_stack: self.call_stack.clone(),
+        })
+    }
+}
+
+/// Returns the parsed expressions of a module that is bundled with Kari
+///
+/// The bundled modules never change, so they only need to be parsed once per
+/// process. Only the version with annotations is cached, as stripping them is
+/// meant for single production runs.
+fn bundled(
+    cell: &OnceLock<Arc<Vec<Expression>>>,
+    name: &str,
+    source: &str,
+    strip_annotations: bool,
+) -> Arc<Vec<Expression>> {
+    let parse = || {
+        let mut stream = source.as_bytes();
+        let mut pipeline = Pipeline::new(name.into(), &mut stream);
+        if strip_annotations {
+            pipeline.parser.strip_annotations();
+        }
+
+        let expressions = pipeline
+            .parser
+            .expressions(&mut String::new())
+            .expect("Failed to parse bundled module");
+
+        Arc::new(expressions)
+    };
+
+    if strip_annotations {
+        return parse();
+    }
+
+    cell.get_or_init(parse).clone()
 }
 
 impl<Host> Context<Host> for Interpreter<Host> {
@@ -185,6 +444,10 @@ impl<Host> Context<Host> for Interpreter<Host> {
         &mut self.call_stack
     }
 
+    fn test_cases(&mut self) -> &mut Vec<TestCase> {
+        &mut self.test_cases
+    }
+
     fn output(&mut self) -> &mut dyn io::Write {
         &mut self.stdout
     }
@@ -198,21 +461,13 @@ impl<Host> Context<Host> for Interpreter<Host> {
 
         let module_scope = self.functions.new_scope(scope, name.clone());
 
-        let stream = match self.streams.get_mut(&name) {
-            Some(stream) => stream,
+        let expressions = match self.modules.get(&name) {
+            Some(module) => Vec::clone(module),
             None => return Err(context::Error::ModuleNotFound(name)),
         };
 
-        let mut pipeline = Pipeline::new(name, stream.as_mut());
-        let mut expressions = Vec::new();
-        let mut source = String::new();
-
-        loop {
-            match pipeline.parser.next_expression(&mut source) {
-                Ok(expression) => expressions.push(expression),
-                Err(parser::Error::EndOfStream) => break,
-                Err(error) => return Err(error.into()),
-            }
+        for observer in &mut self.observers {
+            observer.load_module(&name);
         }
 
         let start = expressions
@@ -228,6 +483,34 @@ impl<Host> Context<Host> for Interpreter<Host> {
         ))
     }
 
+    fn define(
+        &mut self,
+        scope: Scope,
+        name: String,
+        args: &[&'static dyn Type],
+        function: Function<Host>,
+    ) -> Result<(), DefineError> {
+        if !self.observers.is_empty() {
+            let span = match &function {
+                Function::Builtin(_) => None,
+                Function::UserDefined { body, .. } => body.span.as_ref(),
+            };
+            let definition = Definition {
+                name: &name,
+                scope: &self.functions.scope_name(scope),
+                signature: args,
+                span,
+            };
+
+            for observer in &mut self.observers {
+                observer.define_function(&definition);
+            }
+        }
+
+        self.functions.define(scope, name, args, function)?;
+        Ok(())
+    }
+
     fn evaluate_value(
         &mut self,
         host: &mut Host,
@@ -235,34 +518,13 @@ impl<Host> Context<Host> for Interpreter<Host> {
         value: value::Any,
     ) -> Result<(), context::Error> {
         if let value::Kind::Word(word) = value.kind {
-            self.call_stack.frames.push(StackFrame {
-                scope,
-                span: value.span,
-            });
-
-            match self.functions.get(scope, &word, &self.stack) {
-                Ok(f) => match f {
-                    Function::Builtin(f) => {
-                        f(host, self, scope)?;
-                    }
-                    Function::UserDefined { body } => {
-                        self.evaluate_list(host, body)?;
-                    }
-                },
-                Err(functions::GetError {
-                    candidates, scope, ..
-                }) => {
-                    return Err(context::Error::FunctionNotFound {
-                        name: word,
-                        stack: self.stack.clone(),
-                        candidates,
-                        scope,
-                    });
-                }
-            }
-
-            self.call_stack.frames.pop();
+            self.call(host, scope, &word, value.span, |functions, stack| {
+                functions.resolve(scope, &word, stack)
+            })?;
         } else {
+            for observer in &mut self.observers {
+                observer.push_value(&value);
+            }
             self.stack.push::<value::Any>(value);
         }
 
@@ -274,12 +536,290 @@ impl<Host> Context<Host> for Interpreter<Host> {
         host: &mut Host,
         list: v::List,
     ) -> Result<(), context::Error> {
-        let scope = list.inner.scope;
+        for observer in &mut self.observers {
+            observer.evaluate_list(list.span.as_ref());
+        }
+
+        let chunk = self.list_chunk(list);
+        self.execute(host, &chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io, rc::Rc};
+
+    use crate::{
+        debugger::{Debugger, Pause, Resume},
+        observer::{Call, Definition, Observer},
+        source::Span,
+        value,
+    };
+
+    use super::{ErrorKind, Interpreter};
+
+    fn template() -> Interpreter<()> {
+        Interpreter::new(Box::new(io::sink()), Box::new(io::sink()))
+            .with_default_builtins()
+            .with_default_prelude(&mut ())
+            .unwrap()
+            .with_default_modules()
+    }
+
+    fn fork(template: &Interpreter<()>) -> Interpreter<()> {
+        template.fork(Box::new(io::sink()), Box::new(io::sink()))
+    }
+
+    #[test]
+    fn forks_should_be_isolated_from_each_other() {
+        let template = template();
+
+        let program = "\"std\" import [ 1 ] :a define a\n".as_bytes();
+        let stack = fork(&template)
+            .run(&mut (), "a".into(), Box::new(program))
+            .unwrap();
+        assert_eq!(stack[0].kind, value::Kind::Number(1));
+
+        let program = "a\n".as_bytes();
+        let result =
+            fork(&template).run(&mut (), "b".into(), Box::new(program));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn stripped_annotations_should_not_be_checked() {
+        let program = || "\"a\" [: number :]\n".as_bytes();
 
-        for expr in list {
-            self.evaluate_value(host, scope, expr)?;
+        let result = fork(&template()).run(
+            &mut (),
+            "checked".into(),
+            Box::new(program()),
+        );
+        assert!(result.is_err());
+
+        let stack =
+            Interpreter::new(Box::new(io::sink()), Box::new(io::sink()))
+                .with_annotations_stripped()
+                .with_default_builtins()
+                .run(&mut (), "stripped".into(), Box::new(program()))
+                .unwrap();
+        assert_eq!(stack.len(), 1);
+    }
+
+    #[test]
+    fn debugger_should_step_into_over_and_out_of_functions() {
+        struct Script {
+            steps: Vec<Resume>,
+            log: Rc<RefCell<Vec<(String, usize)>>>,
         }
 
-        Ok(())
+        impl Debugger for Script {
+            fn is_breakpoint(&mut self, _: &str, _: Option<&Span>) -> bool {
+                false
+            }
+
+            fn stop_on_entry(&self) -> bool {
+                true
+            }
+
+            fn pause(&mut self, pause: Pause) -> Resume {
+                self.log
+                    .borrow_mut()
+                    .push((pause.word.into(), pause.frames.len()));
+                self.steps.remove(0)
+            }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let debugger = Script {
+            steps: vec![
+                Resume::StepOver,
+                Resume::StepInto,
+                Resume::StepOut,
+                Resume::Continue,
+            ],
+            log: log.clone(),
+        };
+
+        let program = "[ 1 + ] :inc define\n1 inc 2 +\n".as_bytes();
+        let stack = template()
+            .with_debugger(Box::new(debugger))
+            .run(&mut (), "program".into(), Box::new(program))
+            .unwrap();
+
+        assert_eq!(stack[0].kind, value::Kind::Number(4));
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                ("define".into(), 0),
+                ("inc".into(), 0),
+                ("+".into(), 1),
+                ("+".into(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn observers_should_be_notified_of_every_step() {
+        struct Log(Rc<RefCell<Vec<String>>>);
+
+        impl Observer for Log {
+            fn evaluate_word(&mut self, word: &str, _: Option<&Span>) {
+                self.0.borrow_mut().push(format!("word {}", word));
+            }
+
+            fn push_value(&mut self, value: &value::Any) {
+                self.0.borrow_mut().push(format!("push {}", value.kind));
+            }
+
+            fn enter_function(&mut self, call: &Call) {
+                self.0.borrow_mut().push(format!(
+                    "enter {} {} {}",
+                    call.name,
+                    call.signature.len(),
+                    call.depth,
+                ));
+            }
+
+            fn exit_function(&mut self, call: &Call) {
+                self.0.borrow_mut().push(format!("exit {}", call.name));
+            }
+
+            fn define_function(&mut self, definition: &Definition) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("define {}", definition.name));
+            }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let program = "[ 1 + ] :inc define\n1 inc\n".as_bytes();
+        template()
+            .with_observer(Box::new(Log(log.clone())))
+            .run(&mut (), "program".into(), Box::new(program))
+            .unwrap();
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "push [ 1 + ]",
+                "push :inc",
+                "word define",
+                "enter define 2 1",
+                "define inc",
+                "exit define",
+                "push 1",
+                "word inc",
+                "enter inc 0 1",
+                "push 1",
+                "word +",
+                "enter + 2 2",
+                "exit +",
+                "exit inc",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cases_should_run_isolated_after_the_program() {
+        let program = "\
+            1\n\
+            [ fail ] \"fails\" test\n\
+            [ 2 ] \"passes\" test\n\
+        "
+        .as_bytes();
+
+        let results = template()
+            .test(&mut (), "program".into(), Box::new(program))
+            .unwrap();
+
+        let results: Vec<_> = results
+            .iter()
+            .map(|result| {
+                let error = result.result.as_ref().err();
+                (result.name.as_str(), error.map(|error| error.kind.name()))
+            })
+            .collect();
+        assert_eq!(results, vec![("fails", Some("failure")), ("passes", None)]);
+    }
+
+    #[test]
+    fn hosts_should_be_able_to_match_on_errors_passed_to_fail_with() {
+        let program = "\
+            :not_found [ :path \"config.kr\" ] error fail_with\n\
+        "
+        .as_bytes();
+
+        let error = template()
+            .run(&mut (), "program".into(), Box::new(program))
+            .unwrap_err();
+
+        let error = match &error.failure().unwrap().kind {
+            value::Kind::Error(error) => error,
+            kind => panic!("Unexpected failure: {}", kind),
+        };
+        assert_eq!(error.kind, "not_found");
+        assert_eq!(
+            error.field("path").unwrap().kind,
+            value::Kind::String("config.kr".into())
+        );
+    }
+
+    #[test]
+    fn checker_should_know_that_import_takes_a_module_name() {
+        let check = |program: &str| -> Vec<&'static str> {
+            template()
+                .check("program".into(), &mut program.as_bytes())
+                .iter()
+                .map(|error| match &*error.kind {
+                    ErrorKind::Check(error) => error.name(),
+                    kind => panic!("Unexpected error: {:?}", kind),
+                })
+                .collect()
+        };
+
+        assert_eq!(check("1 \"std\" import 2 + print\n"), [""; 0]);
+        assert_eq!(check("\"std\" import +\n"), ["stack_underflow"]);
+    }
+
+    #[test]
+    fn call_site_caches_should_survive_loop_iterations() {
+        let mut program = "0 [ 1 + ] [ clone 10 < ] while\n".as_bytes();
+
+        let mut interpreter = fork(&template());
+        interpreter
+            .evaluate_program(&mut (), "program".into(), &mut program)
+            .unwrap();
+        assert_eq!(interpreter.stack.len(), 1);
+
+        // `+` and `<` are resolved against the stack, once per call site. All
+        // later iterations hit the cache.
+        let mut hits: Vec<_> = interpreter
+            .list_chunks
+            .values()
+            .flat_map(|chunk| chunk.words.iter())
+            .filter(|word| word.name == "+" || word.name == "<")
+            .map(|word| (word.name.as_str(), word.cache.hits()))
+            .collect();
+        hits.sort();
+        assert_eq!(hits, [("+", 9), ("<", 10)]);
+    }
+
+    #[test]
+    fn list_caches_should_not_grow_with_new_scopes() {
+        let mut program = "\
+            0 [ \"[ 1 ] eval\" parse eval drop 1 + ] [ clone 10 < ] while\n\
+        "
+        .as_bytes();
+
+        let mut interpreter = fork(&template());
+        interpreter
+            .evaluate_program(&mut (), "program".into(), &mut program)
+            .unwrap();
+
+        // `parse` creates a new scope each time, but the parsed lists are
+        // still the same source code.
+        assert_eq!(interpreter.list_chunks.len(), 3);
     }
 }
@@ -2,15 +2,43 @@ use std::{collections::HashMap, fmt, io};
 
 use termion::{color, style};
 
-use crate::{call_stack::CallStack, context, pipeline::parser, source::Span};
+use crate::{
+    call_stack::CallStack, check, context, pipeline::parser, source::Span,
+    value,
+};
 
 #[derive(Debug)]
 pub struct Error {
-    pub kind: ErrorKind,
+    /// The kind of error, boxed to keep results small on the happy path
+    pub kind: Box<ErrorKind>,
     pub call_stack: CallStack,
 }
 
 impl Error {
+    /// The location that the error originates from, if known
+    pub fn span(&self) -> Option<&Span> {
+        let mut spans = Vec::new();
+        self.kind.spans(&mut spans);
+
+        spans.first().copied().or_else(|| {
+            self.call_stack
+                .frames
+                .last()
+                .and_then(|frame| frame.span.as_ref())
+        })
+    }
+
+    /// The value that was passed to `fail_with` or `throw`, if this error was
+    /// caused by it
+    pub fn failure(&self) -> Option<&value::Any> {
+        match &*self.kind {
+            ErrorKind::Context(context::Error::Failure(value)) => {
+                value.as_ref()
+            }
+            _ => None,
+        }
+    }
+
     pub fn print(
         &self,
         sources: &HashMap<String, String>,
@@ -60,7 +88,8 @@ impl Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.kind {
+        match &*self.kind {
+            ErrorKind::Check(error) => error.fmt(f),
             ErrorKind::Context(error) => error.fmt(f),
             ErrorKind::Parser(error) => error.fmt(f),
         }
@@ -69,13 +98,24 @@ impl fmt::Display for Error {
 
 #[derive(Debug)]
 pub enum ErrorKind {
+    Check(check::Error),
     Context(context::Error),
     Parser(parser::Error),
 }
 
 impl ErrorKind {
+    /// A short name that identifies the kind of error, like `type`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Check(error) => error.name(),
+            ErrorKind::Context(error) => error.name(),
+            ErrorKind::Parser(_) => "parser",
+        }
+    }
+
     pub fn spans<'r>(&'r self, spans: &mut Vec<&'r Span>) {
         match self {
+            ErrorKind::Check(error) => error.spans(spans),
             ErrorKind::Context(error) => error.spans(spans),
             ErrorKind::Parser(error) => error.spans(spans),
         }
@@ -84,7 +124,7 @@ impl ErrorKind {
     pub fn write_hint(&self, stderr: &mut dyn io::Write) -> io::Result<()> {
         match self {
             ErrorKind::Context(error) => error.write_hint(stderr),
-            ErrorKind::Parser(_) => Ok(()),
+            ErrorKind::Check(_) | ErrorKind::Parser(_) => Ok(()),
         }
     }
 }
@@ -109,11 +149,10 @@ fn print_source(
     let source = sources.get(&span.stream_name).unwrap();
 
     let start = source[..span.start.index].rfind('\n').unwrap_or(0);
-    let end = span.end.index
-        + source[span.end.index..]
-            .find('\n')
-            .unwrap_or(source.len() - 1)
-        + 1;
+    // The rest of the line might not have been read yet.
+    let end = source[span.end.index..]
+        .find('\n')
+        .map_or(source.len(), |i| span.end.index + i + 1);
 
     let source = &source[start..end];
 
@@ -0,0 +1,344 @@
+use std::rc::Rc;
+
+use crate::{
+    call_stack::StackFrame,
+    context,
+    debugger::{Debugger, Frame, Pause, Resume},
+    functions::{self, Effect, Function, Functions, Scope, Slot},
+    observer::{Call, Observer},
+    pipeline::compiler::{Chunk, Compiler, Instruction},
+    source::Span,
+    stack::Stack,
+    value::{types::Typed as _, v},
+};
+
+use super::Interpreter;
+
+impl<Host> Interpreter<Host> {
+    /// Execute a compiled list
+    pub(super) fn execute(
+        &mut self,
+        host: &mut Host,
+        chunk: &Chunk,
+    ) -> Result<(), context::Error> {
+        for instruction in &chunk.code {
+            match *instruction {
+                Instruction::Push { constant } => {
+                    let value = chunk.constants[constant as usize].clone();
+                    for observer in &mut self.observers {
+                        observer.push_value(&value);
+                    }
+                    self.stack.push_raw(value);
+                }
+                Instruction::Call { word, slot } => {
+                    let word = &chunk.words[word as usize];
+
+                    // If functions have been defined since the chunk was
+                    // compiled, one of them might shadow the function we
+                    // resolved back then.
+                    let is_current = chunk.is_current(&self.functions);
+
+                    self.call(
+                        host,
+                        chunk.scope,
+                        &word.name,
+                        word.span.clone(),
+                        |functions, stack| {
+                            if is_current {
+                                Ok(slot)
+                            } else {
+                                functions.resolve(
+                                    chunk.scope,
+                                    &word.name,
+                                    stack,
+                                )
+                            }
+                        },
+                    )?;
+                }
+                Instruction::Dispatch { word } => {
+                    let word = &chunk.words[word as usize];
+
+                    self.call(
+                        host,
+                        chunk.scope,
+                        &word.name,
+                        word.span.clone(),
+                        |functions, stack| {
+                            word.cache.resolve(
+                                functions,
+                                chunk.scope,
+                                &word.name,
+                                stack,
+                            )
+                        },
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Call the function that the word refers to
+    ///
+    /// `resolve` is called to find the function, after the call has been
+    /// pushed to the call stack.
+    pub(super) fn call(
+        &mut self,
+        host: &mut Host,
+        scope: Scope,
+        word: &str,
+        span: Option<Span>,
+        resolve: impl FnOnce(
+            &Functions<Function<Host>>,
+            &Stack,
+        ) -> Result<Slot, functions::GetError>,
+    ) -> Result<(), context::Error> {
+        for observer in &mut self.observers {
+            observer.evaluate_word(word, span.as_ref());
+        }
+
+        if self.debugging.is_some() {
+            self.pause_if_needed(word, span.as_ref());
+        }
+
+        self.call_stack.frames.push(StackFrame { scope, span });
+
+        let slot = resolve(&self.functions, &self.stack).map_err(
+            |functions::GetError { candidates, scope }| {
+                context::Error::FunctionNotFound {
+                    name: word.into(),
+                    stack: self.stack.clone(),
+                    candidates,
+                    scope,
+                }
+            },
+        )?;
+
+        self.notify_call(word, slot, Observer::enter_function);
+
+        match self.functions.function(slot) {
+            Function::Builtin(f) => {
+                let f = *f;
+                f(host, self, scope)?;
+            }
+            Function::UserDefined { body, effect } => {
+                for observer in &mut self.observers {
+                    observer.evaluate_list(body.span.as_ref());
+                }
+
+                // Remember where the inputs start, so we can check what the
+                // function left on the stack, once it returns.
+                let effect = effect.clone().map(|effect| {
+                    let base =
+                        self.stack.len().saturating_sub(effect.inputs.len());
+                    (effect, base)
+                });
+
+                let chunk = self.chunk(slot);
+                self.execute(host, &chunk)?;
+
+                if let Some((effect, base)) = effect {
+                    self.check_effect(word, slot, effect, base)?;
+                }
+            }
+        }
+
+        self.notify_call(word, slot, Observer::exit_function);
+
+        self.call_stack.frames.pop();
+
+        Ok(())
+    }
+
+    /// Check that a function left the stack as its stack effect declares
+    ///
+    /// `base` is the length of the stack below the function's inputs, at the
+    /// time it was called.
+    fn check_effect(
+        &self,
+        word: &str,
+        slot: Slot,
+        effect: Rc<Effect>,
+        base: usize,
+    ) -> Result<(), context::Error> {
+        let len = self.stack.len();
+
+        let matches = len == base + effect.outputs.len()
+            && self
+                .stack
+                .peek()
+                .zip(effect.outputs.iter().rev())
+                .all(|(value, expected)| **expected == *value.get_type());
+        if matches {
+            return Ok(());
+        }
+
+        let mut left: Vec<_> = self
+            .stack
+            .peek()
+            .take(len.saturating_sub(base))
+            .cloned()
+            .collect();
+        left.reverse();
+
+        let definition = match self.functions.function(slot) {
+            Function::Builtin(_) => None,
+            Function::UserDefined { body, .. } => {
+                body.span.clone().map(Box::new)
+            }
+        };
+
+        Err(context::Error::StackEffect {
+            name: word.into(),
+            effect,
+            consumed: base.saturating_sub(len),
+            left,
+            definition,
+        })
+    }
+
+    fn notify_call(
+        &mut self,
+        word: &str,
+        slot: Slot,
+        notify: fn(&mut (dyn Observer + 'static), &Call),
+    ) {
+        if self.observers.is_empty() {
+            return;
+        }
+
+        let definition = match self.functions.function(slot) {
+            Function::Builtin(_) => None,
+            Function::UserDefined { body, .. } => body.span.as_ref(),
+        };
+        let call = Call {
+            name: word,
+            span: self
+                .call_stack
+                .frames
+                .last()
+                .and_then(|frame| frame.span.as_ref()),
+            signature: self.functions.signature(slot),
+            definition,
+            depth: self.call_stack.frames.len(),
+        };
+
+        for observer in &mut self.observers {
+            notify(observer.as_mut(), &call);
+        }
+    }
+
+    fn pause_if_needed(&mut self, word: &str, span: Option<&Span>) {
+        let debugging = match &mut self.debugging {
+            Some(debugging) => debugging,
+            None => return,
+        };
+
+        let depth = self.call_stack.frames.len();
+
+        let is_step_done = match debugging.mode {
+            Mode::Continue => false,
+            Mode::StepInto => true,
+            Mode::StepOver(step_depth) => depth <= step_depth,
+            Mode::StepOut(step_depth) => depth < step_depth,
+        };
+        if !is_step_done && !debugging.debugger.is_breakpoint(word, span) {
+            return;
+        }
+
+        let frames = self
+            .call_stack
+            .frames
+            .iter()
+            .map(|frame| Frame {
+                scope: self.functions.scope_name(frame.scope),
+                span: frame.span.as_ref(),
+            })
+            .collect();
+
+        let resume = debugging.debugger.pause(Pause {
+            word,
+            span,
+            stack: &self.stack,
+            frames,
+            sources: &self.sources,
+        });
+
+        debugging.mode = match resume {
+            Resume::Continue => Mode::Continue,
+            Resume::StepInto => Mode::StepInto,
+            Resume::StepOver => Mode::StepOver(depth),
+            Resume::StepOut => Mode::StepOut(depth),
+        };
+    }
+
+    /// Returns the compiled body of the user-defined function in the slot
+    ///
+    /// Bodies are compiled on first use. They are recompiled, if functions
+    /// have been defined since then.
+    fn chunk(&mut self, slot: Slot) -> Rc<Chunk> {
+        if let Some(chunk) = self.chunks.get(&slot) {
+            if chunk.is_current(&self.functions) {
+                return chunk.clone();
+            }
+        }
+
+        let body = match self.functions.function(slot) {
+            Function::UserDefined { body, .. } => body.clone(),
+            Function::Builtin(_) => {
+                panic!("Tried to compile builtin function")
+            }
+        };
+
+        let chunk = Rc::new(Compiler::new(&self.functions).compile(body));
+        self.chunks.insert(slot, chunk.clone());
+
+        chunk
+    }
+
+    /// Returns the compiled form of a list that is evaluated, like the body of
+    /// a `while`
+    ///
+    /// Such lists are evaluated again and again, so they are cached by their
+    /// span, keeping the caches at their call sites. Only the latest chunk
+    /// for each span is kept, so lists that are evaluated in a new scope each
+    /// time, like those created by `parse`, don't grow the cache.
+    pub(super) fn list_chunk(&mut self, list: v::List) -> Rc<Chunk> {
+        let key = match &list.span {
+            Some(span) => span.clone(),
+            None => {
+                return Rc::new(Compiler::new(&self.functions).compile(list))
+            }
+        };
+
+        if let Some(chunk) = self.list_chunks.get(&key) {
+            if chunk.is_compiled_from(&list)
+                && chunk.is_current(&self.functions)
+            {
+                return chunk.clone();
+            }
+        }
+
+        let chunk = Rc::new(Compiler::new(&self.functions).compile(list));
+        self.list_chunks.insert(key, chunk.clone());
+
+        chunk
+    }
+}
+
+pub(super) struct Debugging {
+    pub(super) debugger: Box<dyn Debugger>,
+    pub(super) mode: Mode,
+}
+
+/// Where the debugger should pause next, apart from breakpoints
+///
+/// The depths refer to the length of the call stack at the time of the step.
+pub(super) enum Mode {
+    Continue,
+    StepInto,
+    StepOver(usize),
+    StepOut(usize),
+}
@@ -1,11 +1,16 @@
 pub mod builtins;
 pub mod call_stack;
+pub mod check;
 pub mod context;
+pub mod debugger;
+pub mod format;
 pub mod functions;
 pub mod interpreter;
+pub mod observer;
 pub mod pipeline;
 pub mod source;
 pub mod stack;
+pub mod test_case;
 pub mod value;
 
 pub mod prelude {
@@ -0,0 +1,80 @@
+pub mod coverage;
+pub mod profiler;
+pub mod tracer;
+
+use crate::{source::Span, value, value::types::Type};
+
+pub use self::{coverage::Coverage, profiler::Profiler, tracer::Tracer};
+
+/// Observes the evaluation of a program
+///
+/// Observers can be attached to an interpreter using
+/// [`Interpreter::with_observer`]. The interpreter notifies them of every step
+/// it takes. Observers can't influence the evaluation. Use a
+/// [`Debugger`](crate::debugger::Debugger) for that.
+///
+/// All methods have empty default implementations, so implementations only
+/// need to provide the ones they're interested in.
+///
+/// [`Interpreter::with_observer`]: crate::Interpreter::with_observer
+pub trait Observer {
+    /// A word is about to be evaluated
+    fn evaluate_word(&mut self, _word: &str, _span: Option<&Span>) {}
+
+    /// A list is about to be evaluated
+    ///
+    /// This happens when a user-defined function is called, or when a builtin
+    /// evaluates a list.
+    fn evaluate_list(&mut self, _span: Option<&Span>) {}
+
+    /// A value that isn't a word has been pushed to the stack
+    fn push_value(&mut self, _value: &value::Any) {}
+
+    /// A word has been resolved, and its function is about to be called
+    fn enter_function(&mut self, _call: &Call) {}
+
+    /// A function has returned
+    ///
+    /// Not called, if the function returned an error.
+    fn exit_function(&mut self, _call: &Call) {}
+
+    /// A function has been defined
+    fn define_function(&mut self, _definition: &Definition) {}
+
+    /// A module has been loaded
+    fn load_module(&mut self, _name: &str) {}
+}
+
+/// A call to a function
+pub struct Call<'r> {
+    /// The word that the function was called by
+    pub name: &'r str,
+
+    /// The location of the word that the function was called by
+    pub span: Option<&'r Span>,
+
+    /// The types that the function was defined with
+    pub signature: &'r [&'static dyn Type],
+
+    /// Where the function was defined
+    ///
+    /// This is the span of the function's body. `None` for builtins.
+    pub definition: Option<&'r Span>,
+
+    /// The length of the call stack, including this call
+    pub depth: usize,
+}
+
+/// The definition of a function
+pub struct Definition<'r> {
+    pub name: &'r str,
+
+    /// The name of the scope that the function was defined in
+    pub scope: &'r str,
+
+    /// The types that the function was defined with
+    pub signature: &'r [&'static dyn Type],
+
+    /// The span of the function's body. `None` for builtins.
+    pub span: Option<&'r Span>,
+}
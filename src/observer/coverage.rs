@@ -0,0 +1,352 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{self, Write},
+    rc::Rc,
+};
+
+use termion::{color, style};
+
+use crate::{
+    pipeline::Pipeline,
+    source::{Expression, ExpressionKind, Position, Span},
+};
+
+use super::{Definition, Observer};
+
+/// Records which words and lists have been evaluated
+///
+/// Like [`Profiler`](super::Profiler), this is a handle to shared data. The
+/// same coverage can be attached to multiple interpreters, to record the
+/// coverage of a whole test run.
+///
+/// Which words and lists exist is determined by parsing the sources that are
+/// passed to the reporting methods. Functions are only known, if their
+/// definition has been observed.
+#[derive(Clone, Default)]
+pub struct Coverage {
+    hits: Rc<RefCell<Hits>>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the coverage of all sources in the lcov tracefile format
+    pub fn write_lcov(
+        &self,
+        sources: &HashMap<String, String>,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        writeln!(output, "TN:")?;
+
+        for stream in self.streams(sources) {
+            writeln!(output, "SF:{}", stream.name)?;
+
+            for function in &stream.functions {
+                writeln!(output, "FN:{},{}", function.line + 1, function.name)?;
+            }
+            for function in &stream.functions {
+                writeln!(output, "FNDA:{},{}", function.hits, function.name)?;
+            }
+            writeln!(output, "FNF:{}", stream.functions.len())?;
+            writeln!(output, "FNH:{}", stream.functions_hit())?;
+
+            for (line, hits) in &stream.lines {
+                writeln!(output, "DA:{},{}", line + 1, hits)?;
+            }
+            writeln!(output, "LF:{}", stream.lines.len())?;
+            writeln!(output, "LH:{}", stream.lines_hit())?;
+
+            writeln!(output, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the source code of all streams, annotated with hit counts
+    ///
+    /// Lines that contain words are prefixed with the number of times they
+    /// were evaluated. Lines that were never evaluated are highlighted.
+    pub fn write_annotated(
+        &self,
+        sources: &HashMap<String, String>,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        for stream in self.streams(sources) {
+            writeln!(
+                output,
+                "{}{}{}{}",
+                style::Bold,
+                color::Fg(color::Cyan),
+                stream.name,
+                style::Reset,
+            )?;
+
+            for (i, line) in stream.source.lines().enumerate() {
+                match stream.lines.get(&i) {
+                    Some(0) => writeln!(
+                        output,
+                        "{}{:>8} | {}{}",
+                        color::Fg(color::Red),
+                        0,
+                        line,
+                        color::Fg(color::Reset),
+                    )?,
+                    Some(hits) => writeln!(output, "{:>8} | {}", hits, line)?,
+                    None => writeln!(output, "{:>8} | {}", "", line)?,
+                }
+            }
+
+            writeln!(output)?;
+        }
+
+        self.write_summary(sources, output)
+    }
+
+    /// Write the percentage of covered lines, functions, and lists per stream
+    pub fn write_summary(
+        &self,
+        sources: &HashMap<String, String>,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        writeln!(
+            output,
+            "{:>16} {:>16} {:>16}  stream",
+            "lines", "functions", "lists",
+        )?;
+
+        for stream in self.streams(sources) {
+            writeln!(
+                output,
+                "{:>16} {:>16} {:>16}  {}",
+                ratio(stream.lines_hit(), stream.lines.len()),
+                ratio(stream.functions_hit(), stream.functions.len()),
+                ratio(stream.lists_hit, stream.lists),
+                stream.name,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn streams<'r>(
+        &self,
+        sources: &'r HashMap<String, String>,
+    ) -> Vec<Stream<'r>> {
+        let hits = self.hits.borrow();
+
+        let mut names: Vec<_> = sources.keys().collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let source = &sources[name];
+
+                // Streams that can't be parsed couldn't have been evaluated
+                // either, so there's nothing to report.
+                let mut stream = source.as_bytes();
+                let expressions = Pipeline::new(name.clone(), &mut stream)
+                    .parser
+                    .expressions(&mut String::new())
+                    .ok()?;
+
+                let mut stream = Stream {
+                    name,
+                    source,
+                    lines: BTreeMap::new(),
+                    functions: Vec::new(),
+                    lists: 0,
+                    lists_hit: 0,
+                };
+                for expression in &expressions {
+                    stream.add(expression, &hits);
+                }
+
+                Some(stream)
+            })
+            .collect()
+    }
+}
+
+impl Observer for Coverage {
+    fn evaluate_word(&mut self, _: &str, span: Option<&Span>) {
+        if let Some(span) = span {
+            *self.hits.borrow_mut().words.entry(key(span)).or_default() += 1;
+        }
+    }
+
+    fn evaluate_list(&mut self, span: Option<&Span>) {
+        if let Some(span) = span {
+            *self.hits.borrow_mut().lists.entry(key(span)).or_default() += 1;
+        }
+    }
+
+    fn define_function(&mut self, definition: &Definition) {
+        if let Some(span) = definition.span {
+            self.hits
+                .borrow_mut()
+                .functions
+                .entry(key(span))
+                .or_default()
+                .insert(definition.name.to_string());
+        }
+    }
+}
+
+/// Identifies an expression by the start of its span
+type Key = (String, Position);
+
+fn key(span: &Span) -> Key {
+    (span.stream_name.clone(), span.start)
+}
+
+#[derive(Default)]
+struct Hits {
+    words: HashMap<Key, u64>,
+    lists: HashMap<Key, u64>,
+
+    /// The names of the functions that have been defined with each list
+    functions: HashMap<Key, BTreeSet<String>>,
+}
+
+/// The coverage of a single stream
+struct Stream<'r> {
+    name: &'r str,
+    source: &'r str,
+
+    /// The number of evaluations of each line that contains words
+    ///
+    /// A line counts as evaluated as often as its most evaluated word.
+    lines: BTreeMap<usize, u64>,
+
+    functions: Vec<Function>,
+    lists: usize,
+    lists_hit: usize,
+}
+
+impl Stream<'_> {
+    fn add(&mut self, expression: &Expression, hits: &Hits) {
+        let span = match &expression.span {
+            Some(span) => span,
+            None => return,
+        };
+
+        match &expression.kind {
+            ExpressionKind::Word(_) => {
+                let count =
+                    hits.words.get(&key(span)).copied().unwrap_or_default();
+
+                let line = self.lines.entry(span.start.line).or_default();
+                *line = (*line).max(count);
+            }
+            ExpressionKind::List(expressions) => {
+                let count =
+                    hits.lists.get(&key(span)).copied().unwrap_or_default();
+
+                self.lists += 1;
+                if count > 0 {
+                    self.lists_hit += 1;
+                }
+
+                if let Some(names) = hits.functions.get(&key(span)) {
+                    for name in names {
+                        self.functions.push(Function {
+                            name: name.clone(),
+                            line: span.start.line,
+                            hits: count,
+                        });
+                    }
+                }
+
+                for expression in expressions {
+                    self.add(expression, hits);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|&&hits| hits > 0).count()
+    }
+
+    fn functions_hit(&self) -> usize {
+        self.functions
+            .iter()
+            .filter(|function| function.hits > 0)
+            .count()
+    }
+}
+
+struct Function {
+    name: String,
+    line: usize,
+    hits: u64,
+}
+
+fn ratio(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return String::from("-");
+    }
+
+    format!(
+        "{}/{} ({:.0}%)",
+        hit,
+        total,
+        hit as f64 / total as f64 * 100.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io};
+
+    use crate::Interpreter;
+
+    use super::Coverage;
+
+    #[test]
+    fn coverage_should_record_evaluated_lines_and_functions() {
+        let coverage = Coverage::new();
+
+        let source = "[ 1 + ] :inc define\n[\n    2 +\n] :add2 define\n1 inc\n";
+
+        Interpreter::new(Box::new(io::sink()), Box::new(io::sink()))
+            .with_default_builtins()
+            .with_observer(Box::new(coverage.clone()))
+            .run(&mut (), "program".into(), Box::new(source.as_bytes()))
+            .unwrap();
+
+        let mut sources = HashMap::new();
+        sources.insert(String::from("program"), String::from(source));
+
+        let mut lcov = Vec::new();
+        coverage.write_lcov(&sources, &mut lcov).unwrap();
+        let lcov = String::from_utf8(lcov).unwrap();
+
+        let lines: Vec<_> = lcov.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "TN:",
+                "SF:program",
+                "FN:1,inc",
+                "FN:2,add2",
+                "FNDA:1,inc",
+                "FNDA:0,add2",
+                "FNF:2",
+                "FNH:1",
+                "DA:1,1",
+                "DA:3,0",
+                "DA:4,1",
+                "DA:5,1",
+                "LF:4",
+                "LH:3",
+                "end_of_record",
+            ]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Write},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use super::{Call, Observer};
+
+/// Records how much time is spent in each function
+///
+/// Functions are identified by their name and the location of their
+/// definition, so functions of the same name that are defined in different
+/// places are profiled separately.
+///
+/// The profiler is a handle to a shared profile. Attach a clone of it to the
+/// interpreter, and use the original to write the results once the program
+/// has finished.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    profile: Rc<RefCell<Profile>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the profile in the folded stacks format
+    ///
+    /// This is the format that flamegraph tools expect. Each line contains a
+    /// call stack, and the time in microseconds that was spent in the
+    /// innermost function of that stack, not counting the functions it
+    /// called.
+    pub fn write_folded(&self, output: &mut dyn Write) -> io::Result<()> {
+        let mut profile = self.profile.borrow_mut();
+        profile.finish();
+
+        let mut lines: Vec<_> = profile
+            .stacks
+            .iter()
+            .map(|(path, time)| {
+                let names: Vec<_> = path
+                    .iter()
+                    .map(|&function| profile.functions[function].name.as_str())
+                    .collect();
+                (names.join(";"), time.as_micros())
+            })
+            .collect();
+        lines.sort();
+
+        for (stack, micros) in lines {
+            writeln!(output, "{} {}", stack, micros)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a summary of all functions, sorted by the total time spent in them
+    pub fn write_summary(&self, output: &mut dyn Write) -> io::Result<()> {
+        let mut profile = self.profile.borrow_mut();
+        profile.finish();
+
+        let mut functions: Vec<_> = profile.functions.iter().collect();
+        functions.sort_by(|a, b| {
+            b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name))
+        });
+
+        writeln!(
+            output,
+            "{:>10} {:>12} {:>12}  function",
+            "calls", "total (ms)", "self (ms)",
+        )?;
+        for function in functions {
+            writeln!(
+                output,
+                "{:>10} {:>12.3} {:>12.3}  {}",
+                function.calls,
+                function.total.as_secs_f64() * 1000.0,
+                function.self_time.as_secs_f64() * 1000.0,
+                function.name,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Observer for Profiler {
+    fn enter_function(&mut self, call: &Call) {
+        let mut profile = self.profile.borrow_mut();
+        let now = Instant::now();
+
+        // If a function returned an error, we never heard of its exit. Close
+        // whatever is still open at this depth.
+        profile.close_until(call.depth - 1, now);
+
+        let name = match call.definition {
+            Some(span) => format!(
+                "{} ({}:{}:{})",
+                call.name,
+                span.stream_name,
+                span.start.line + 1,
+                span.start.column + 1,
+            ),
+            None => call.name.to_string(),
+        };
+        // Semicolons separate the functions in the folded stacks format.
+        let name = name.replace(';', ":");
+
+        let function = match profile.indices.get(&name) {
+            Some(&index) => index,
+            None => {
+                let index = profile.functions.len();
+                profile.indices.insert(name.clone(), index);
+                profile.functions.push(Function {
+                    name,
+                    calls: 0,
+                    total: Duration::ZERO,
+                    self_time: Duration::ZERO,
+                });
+                index
+            }
+        };
+
+        profile.open.push(Open {
+            function,
+            depth: call.depth,
+            start: now,
+            children: Duration::ZERO,
+        });
+    }
+
+    fn exit_function(&mut self, call: &Call) {
+        let mut profile = self.profile.borrow_mut();
+        profile.close_until(call.depth - 1, Instant::now());
+    }
+}
+
+#[derive(Default)]
+struct Profile {
+    functions: Vec<Function>,
+    indices: HashMap<String, usize>,
+
+    /// The time spent in the innermost function of each call stack
+    ///
+    /// Call stacks are identified by the indices of their functions.
+    stacks: HashMap<Vec<usize>, Duration>,
+
+    /// The calls that are currently in progress
+    open: Vec<Open>,
+}
+
+impl Profile {
+    /// Close all calls that are deeper than `depth`
+    fn close_until(&mut self, depth: usize, now: Instant) {
+        while let Some(open) = self.open.last() {
+            if open.depth <= depth {
+                break;
+            }
+
+            let open = self.open.pop().unwrap();
+            let elapsed = now - open.start;
+            let self_time = elapsed.saturating_sub(open.children);
+
+            if let Some(parent) = self.open.last_mut() {
+                parent.children += elapsed;
+            }
+
+            let mut path: Vec<_> =
+                self.open.iter().map(|open| open.function).collect();
+
+            // For recursive functions, only the outermost call counts towards
+            // the total. Otherwise the time would be counted multiple times.
+            let is_recursive = path.contains(&open.function);
+
+            path.push(open.function);
+            *self.stacks.entry(path).or_default() += self_time;
+
+            let function = &mut self.functions[open.function];
+            function.calls += 1;
+            function.self_time += self_time;
+            if !is_recursive {
+                function.total += elapsed;
+            }
+        }
+    }
+
+    /// Close all calls that are still open
+    ///
+    /// Calls remain open, if the program was aborted by an error.
+    fn finish(&mut self) {
+        self.close_until(0, Instant::now());
+    }
+}
+
+struct Function {
+    name: String,
+    calls: u64,
+    total: Duration,
+    self_time: Duration,
+}
+
+struct Open {
+    function: usize,
+    depth: usize,
+    start: Instant,
+    children: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::observer::{Call, Observer as _};
+
+    use super::Profiler;
+
+    fn call(name: &str, depth: usize) -> Call<'_> {
+        Call {
+            name,
+            span: None,
+            signature: &[],
+            definition: None,
+            depth,
+        }
+    }
+
+    #[test]
+    fn profiler_should_record_call_stacks_and_counts() {
+        let profile = Profiler::new();
+        let mut profiler = profile.clone();
+
+        profiler.enter_function(&call("a", 1));
+        profiler.enter_function(&call("b", 2));
+        profiler.exit_function(&call("b", 2));
+        profiler.enter_function(&call("b", 2));
+        profiler.enter_function(&call("c", 3));
+        // `c` returned an error, so we're never told about its exit.
+        profiler.exit_function(&call("b", 2));
+        profiler.exit_function(&call("a", 1));
+        profiler.enter_function(&call("b", 1));
+
+        let mut folded = Vec::new();
+        profile.write_folded(&mut folded).unwrap();
+        let stacks: Vec<_> = String::from_utf8(folded)
+            .unwrap()
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        assert_eq!(stacks, vec!["a", "a;b", "a;b;c", "b"]);
+
+        let profile = profile.profile.borrow();
+        let calls: Vec<_> = profile
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.calls))
+            .collect();
+        assert_eq!(calls, vec![("a", 1), ("b", 3), ("c", 1)]);
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::Write;
+
+use crate::{source::Span, value, value::types::Type};
+
+use super::{Call, Definition, Observer};
+
+/// Writes a trace of every evaluation step
+///
+/// The trace is indented according to the depth of the call stack. Each entry
+/// refers to the location in the source code it originates from.
+pub struct Tracer<W> {
+    output: W,
+
+    /// The depth of the call stack, as of the last call we heard of
+    depth: usize,
+}
+
+impl<W> Tracer<W> {
+    pub fn new(output: W) -> Self {
+        Self { output, depth: 0 }
+    }
+}
+
+impl<W> Tracer<W>
+where
+    W: Write,
+{
+    fn write(&mut self, entry: &str, span: Option<&Span>) {
+        let indentation = "  ".repeat(self.depth);
+
+        // A trace that can't be written is not a reason to abort the program,
+        // so errors are ignored.
+        let _ = match span {
+            Some(span) => writeln!(
+                self.output,
+                "{}{} ({}:{}:{})",
+                indentation,
+                entry,
+                span.stream_name,
+                span.start.line + 1,
+                span.start.column + 1,
+            ),
+            None => writeln!(self.output, "{}{}", indentation, entry),
+        };
+    }
+}
+
+impl<W> Observer for Tracer<W>
+where
+    W: Write,
+{
+    fn evaluate_word(&mut self, word: &str, span: Option<&Span>) {
+        self.write(&format!("word `{}`", word), span);
+    }
+
+    fn push_value(&mut self, value: &value::Any) {
+        self.write(&format!("push `{}`", value.kind), value.span.as_ref());
+    }
+
+    fn enter_function(&mut self, call: &Call) {
+        let kind = match call.definition {
+            Some(_) => "function",
+            None => "builtin",
+        };
+
+        // If a function returned an error, we never heard of its exit. Take
+        // the depth from the call, instead of counting.
+        self.depth = call.depth;
+        self.write(
+            &format!(
+                "enter {} `{}` {}",
+                kind,
+                call.name,
+                format_signature(call.signature),
+            ),
+            call.definition,
+        );
+    }
+
+    fn exit_function(&mut self, call: &Call) {
+        self.depth = call.depth;
+        self.write(&format!("exit `{}`", call.name), None);
+        self.depth = call.depth - 1;
+    }
+
+    fn define_function(&mut self, definition: &Definition) {
+        self.write(
+            &format!(
+                "define `{}` {} in `{}`",
+                definition.name,
+                format_signature(definition.signature),
+                definition.scope,
+            ),
+            definition.span,
+        );
+    }
+
+    fn load_module(&mut self, name: &str) {
+        self.write(&format!("load `{}`", name), None);
+    }
+}
+
+fn format_signature(signature: &[&'static dyn Type]) -> String {
+    let mut formatted = String::from("[ ");
+    for ty in signature {
+        formatted.push_str(ty.name());
+        formatted.push(' ');
+    }
+    formatted.push(']');
+
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io, rc::Rc};
+
+    use crate::Interpreter;
+
+    use super::Tracer;
+
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tracer_should_recover_its_indentation_after_a_caught_error() {
+        let output = Output::default();
+        let program = "[ 1 0 / ] [ drop ] try 2 drop\n".as_bytes();
+
+        Interpreter::new(Box::new(io::sink()), Box::new(io::sink()))
+            .with_default_builtins()
+            .with_observer(Box::new(Tracer::new(output.clone())))
+            .run(&mut (), "program".into(), Box::new(program))
+            .unwrap();
+
+        let trace = String::from_utf8(output.0.take()).unwrap();
+        let lines: Vec<_> = trace
+            .lines()
+            .map(|line| line.split(" (").next().unwrap())
+            .collect();
+        assert_eq!(
+            lines[lines.len() - 5..],
+            [
+                "  exit `try`",
+                "push `2`",
+                "word `drop`",
+                "  enter builtin `drop` [ any ]",
+                "  exit `drop`",
+            ]
+        );
+    }
+}
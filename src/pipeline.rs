@@ -1,8 +1,11 @@
+pub mod compiler;
 pub mod parser;
 pub mod reader;
 pub mod tokenizer;
 
-pub use self::{parser::Parser, reader::Reader, tokenizer::Tokenizer};
+pub use self::{
+    compiler::Compiler, parser::Parser, reader::Reader, tokenizer::Tokenizer,
+};
 
 pub struct Pipeline<R> {
     pub parser: Parser<R>,
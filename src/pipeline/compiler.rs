@@ -0,0 +1,227 @@
+use std::cell::Cell;
+
+use crate::{
+    functions::{Functions, InlineCache, Scope, Slot},
+    source::Span,
+    value::{self, v},
+};
+
+/// Compiles lists into bytecode
+///
+/// Literals are lowered to constants. Words are resolved to the slot of the
+/// function they refer to, if that doesn't depend on the types on the stack.
+/// All other words are left to be resolved when they are executed.
+pub struct Compiler<'r, T> {
+    functions: &'r Functions<T>,
+}
+
+impl<'r, T> Compiler<'r, T>
+where
+    T: Clone,
+{
+    pub fn new(functions: &'r Functions<T>) -> Self {
+        Self { functions }
+    }
+
+    pub fn compile(&self, list: v::List) -> Chunk {
+        let scope = list.inner.scope;
+
+        let mut chunk = Chunk {
+            scope,
+            code: Vec::new(),
+            constants: Vec::new(),
+            words: Vec::new(),
+            generation: Cell::new(self.functions.generation()),
+        };
+
+        for value in list {
+            let instruction = match value.kind {
+                value::Kind::Word(name) => {
+                    let slot = self.functions.resolve_static(scope, &name);
+                    let word = chunk.add_word(Word {
+                        name,
+                        span: value.span,
+                        cache: InlineCache::default(),
+                    });
+
+                    match slot {
+                        Some(slot) => Instruction::Call { word, slot },
+                        None => Instruction::Dispatch { word },
+                    }
+                }
+                _ => Instruction::Push {
+                    constant: chunk.add_constant(value),
+                },
+            };
+
+            chunk.code.push(instruction);
+        }
+
+        chunk
+    }
+}
+
+/// A compiled list
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    /// The scope that the words in this chunk are evaluated in
+    pub scope: Scope,
+
+    pub code: Vec<Instruction>,
+    pub constants: Vec<value::Any>,
+    pub words: Vec<Word>,
+
+    /// The generation of the functions that the chunk was compiled against
+    ///
+    /// If functions have been defined in the chunk's scope since, the resolved
+    /// slots in `Call` instructions might no longer be correct.
+    pub generation: Cell<u64>,
+}
+
+impl Chunk {
+    /// Whether the slots resolved during compilation are still correct
+    pub fn is_current<T>(&self, functions: &Functions<T>) -> bool
+    where
+        T: Clone,
+    {
+        if functions.defined_since(self.scope, self.generation.get()) {
+            return false;
+        }
+
+        self.generation.set(functions.generation());
+        true
+    }
+
+    /// Whether the chunk is the compiled form of the list
+    ///
+    /// Lists that are built or changed at runtime can share a span with the
+    /// list they were made from, so a chunk can't be reused based on the span
+    /// alone.
+    pub fn is_compiled_from(&self, list: &v::List) -> bool {
+        if self.scope != list.inner.scope
+            || self.code.len() != list.inner.items.len()
+        {
+            return false;
+        }
+
+        self.code
+            .iter()
+            .zip(&list.inner.items)
+            .all(|(instruction, value)| match *instruction {
+                Instruction::Push { constant } => {
+                    self.constants[constant as usize].kind == value.kind
+                }
+                Instruction::Call { word, .. }
+                | Instruction::Dispatch { word } => match &value.kind {
+                    value::Kind::Word(name) => {
+                        self.words[word as usize].name == *name
+                    }
+                    _ => false,
+                },
+            })
+    }
+
+    fn add_constant(&mut self, value: value::Any) -> u32 {
+        self.constants.push(value);
+        (self.constants.len() - 1) as u32
+    }
+
+    fn add_word(&mut self, word: Word) -> u32 {
+        self.words.push(word);
+        (self.words.len() - 1) as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Instruction {
+    /// Push a constant to the stack
+    Push { constant: u32 },
+
+    /// Call the function in the given slot
+    Call { word: u32, slot: Slot },
+
+    /// Resolve the word against the stack, then call the function
+    ///
+    /// The result of the resolution is cached in the word.
+    Dispatch { word: u32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Word {
+    pub name: String,
+    pub span: Option<Span>,
+    pub cache: InlineCache,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        functions::Functions,
+        value::{self, t, v, Value as _},
+    };
+
+    use super::{Compiler, Instruction};
+
+    fn list(functions: &Functions<u8>, items: Vec<value::Kind>) -> v::List {
+        let items = items
+            .into_iter()
+            .map(|kind| value::Any::new(kind, None))
+            .collect();
+
+        v::List::new(
+            value::ListInner::from_values(items, functions.root_scope()),
+            None,
+        )
+    }
+
+    #[test]
+    fn it_should_lower_literals_to_constants() {
+        let functions = Functions::<u8>::new();
+        let list = list(
+            &functions,
+            vec![value::Kind::Number(1), value::Kind::Bool(true)],
+        );
+
+        let chunk = Compiler::new(&functions).compile(list);
+
+        assert_eq!(
+            chunk.code,
+            vec![
+                Instruction::Push { constant: 0 },
+                Instruction::Push { constant: 1 },
+            ]
+        );
+        assert_eq!(chunk.constants[1].kind, value::Kind::Bool(true));
+    }
+
+    #[test]
+    fn it_should_only_resolve_words_that_do_not_depend_on_the_stack() {
+        let mut functions = Functions::new();
+        let scope = functions.root_scope();
+
+        functions
+            .define(scope, "a", &[], 1)
+            .unwrap()
+            .define(scope, "b", &[&t::Number], 2)
+            .unwrap();
+
+        let list = list(
+            &functions,
+            vec![
+                value::Kind::Word("a".into()),
+                value::Kind::Word("b".into()),
+                value::Kind::Word("c".into()),
+            ],
+        );
+        let chunk = Compiler::new(&functions).compile(list);
+
+        match chunk.code[0] {
+            Instruction::Call { word: 0, slot } => {
+                assert_eq!(*functions.function(slot), 1);
+            }
+            instruction => panic!("Unexpected instruction: {:?}", instruction),
+        }
+        assert_eq!(chunk.code[1], Instruction::Dispatch { word: 1 });
+        assert_eq!(chunk.code[2], Instruction::Dispatch { word: 2 });
+    }
+}
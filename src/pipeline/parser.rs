@@ -1,4 +1,4 @@
-use std::{fmt, io};
+use std::{fmt, io, mem::discriminant};
 
 use crate::{
     pipeline::tokenizer,
@@ -9,11 +9,29 @@ use super::{reader, Tokenizer};
 
 pub struct Parser<R> {
     tokenizer: Tokenizer<R>,
+    strip_annotations: bool,
+
+    /// An expression that has been parsed, but not returned yet
+    ///
+    /// Stack annotations produce two expressions at once.
+    pending: Option<Expression>,
 }
 
 impl<R> Parser<R> {
     pub fn new(tokenizer: Tokenizer<R>) -> Self {
-        Parser { tokenizer }
+        Parser {
+            tokenizer,
+            strip_annotations: false,
+            pending: None,
+        }
+    }
+
+    /// Leave out stack annotations, like `[: number :]`
+    ///
+    /// Annotations are checked at runtime, which has a cost. Programs that are
+    /// known to work can be run without them.
+    pub fn strip_annotations(&mut self) {
+        self.strip_annotations = true;
     }
 }
 
@@ -25,17 +43,48 @@ where
         &mut self,
         source: &mut String,
     ) -> Result<Expression, Error> {
-        let token = self.tokenizer.next_token(source)?;
+        if let Some(expr) = self.pending.take() {
+            return Ok(expr);
+        }
 
-        let expr = match token.kind {
-            TokenKind::ListOpen => self.parse_list(token.span, source)?,
-            TokenKind::ListClose => {
-                return Err(Error::UnexpectedToken(token));
-            }
-            _ => Expression::from_token(token),
-        };
+        loop {
+            let token = self.tokenizer.next_token(source)?;
+
+            let expr = match token.kind {
+                TokenKind::ListOpen => self.parse_list(token.span, source)?,
+                TokenKind::AnnotationOpen => {
+                    match self.parse_declaration(token, source)? {
+                        Some([list, check]) => {
+                            self.pending = Some(check);
+                            list
+                        }
+                        None => continue,
+                    }
+                }
+                TokenKind::ListClose | TokenKind::AnnotationClose => {
+                    return Err(Error::UnexpectedToken(token));
+                }
+                _ => Expression::from_token(token),
+            };
 
-        Ok(expr)
+            return Ok(expr);
+        }
+    }
+
+    /// Parse all remaining expressions in the stream
+    pub fn expressions(
+        &mut self,
+        source: &mut String,
+    ) -> Result<Vec<Expression>, Error> {
+        let mut expressions = Vec::new();
+
+        loop {
+            match self.next_expression(source) {
+                Ok(expression) => expressions.push(expression),
+                Err(Error::EndOfStream) => return Ok(expressions),
+                Err(error) => return Err(error),
+            }
+        }
     }
 }
 
@@ -44,29 +93,103 @@ where
     R: io::Read,
 {
     fn parse_list(
+        &mut self,
+        span: Option<Span>,
+        source: &mut String,
+    ) -> Result<Expression, Error> {
+        self.parse_items(span, TokenKind::ListClose, source)
+    }
+
+    /// Parse a stack annotation or a stack effect
+    ///
+    /// An annotation, like `[: string number :]`, becomes a list of the
+    /// expected types, followed by a call to `on_stack`, which checks them. A
+    /// stack effect, like `( number -- number )`, becomes a list followed by a
+    /// call to `effect`. Returns `None`, if annotations are stripped.
+    ///
+    /// Stack effects are never stripped, as they also determine which function
+    /// a word refers to.
+    fn parse_declaration(
+        &mut self,
+        open: Token,
+        source: &mut String,
+    ) -> Result<Option<[Expression; 2]>, Error> {
+        let (close, word, strip) = match open.kind {
+            TokenKind::AnnotationOpen => (
+                TokenKind::AnnotationClose,
+                "on_stack",
+                self.strip_annotations,
+            ),
+            TokenKind::EffectOpen => (TokenKind::EffectClose, "effect", false),
+            _ => return Err(Error::UnexpectedToken(open)),
+        };
+
+        let list = self.parse_items(open.span, close, source)?;
+
+        if strip {
+            return Ok(None);
+        }
+
+        let check = Expression {
+            kind: ExpressionKind::Word(String::from(word)),
+            span: list.span.clone(),
+        };
+
+        Ok(Some([list, check]))
+    }
+
+    fn parse_items(
         &mut self,
         mut list_source: Option<Span>,
+        close: TokenKind,
         source: &mut String,
     ) -> Result<Expression, Error> {
         let mut expressions = Vec::new();
 
+        // A stack effect can only be declared at the start of a list. Anywhere
+        // else, parentheses are just words.
+        let mut effect_position = matches!(close, TokenKind::ListClose);
+
         loop {
             let token = self.tokenizer.next_token(source)?;
 
             list_source = list_source.merge(token.span.clone());
 
-            let expr = match token.kind {
-                TokenKind::ListOpen => self.parse_list(token.span, source)?,
-                TokenKind::ListClose => {
+            match token.kind {
+                TokenKind::ListOpen => {
+                    expressions.push(self.parse_list(token.span, source)?);
+                }
+                TokenKind::EffectOpen if !effect_position => {
+                    expressions.push(Expression::from_token(token));
+                }
+                TokenKind::AnnotationOpen | TokenKind::EffectOpen => {
+                    if let Some(declaration) =
+                        self.parse_declaration(token, source)?
+                    {
+                        expressions.extend(declaration);
+                    }
+                }
+                TokenKind::EffectClose
+                    if !matches!(close, TokenKind::EffectClose) =>
+                {
+                    expressions.push(Expression::from_token(token));
+                }
+                TokenKind::ListClose
+                | TokenKind::AnnotationClose
+                | TokenKind::EffectClose => {
+                    if discriminant(&token.kind) != discriminant(&close) {
+                        return Err(Error::UnexpectedToken(token));
+                    }
+
                     return Ok(Expression {
                         kind: ExpressionKind::List(expressions),
                         span: list_source,
                     });
                 }
-                _ => Expression::from_token(token),
-            };
+                _ => expressions.push(Expression::from_token(token)),
+            }
 
-            expressions.push(expr);
+            effect_position = false;
         }
     }
 }
@@ -83,7 +206,21 @@ impl Error {
         match self {
             Error::UnexpectedToken(token) => spans.extend(token.span.as_ref()),
 
-            Error::Tokenizer(_) => (),
+            Error::Tokenizer(error) => error.spans(spans),
+            Error::EndOfStream => (),
+        }
+    }
+}
+
+impl Error {
+    /// Replace all spans in the error
+    ///
+    /// Used when parsing code that doesn't come from a stream whose source is
+    /// available, to point to the origin of that code instead.
+    pub fn replace_spans(&mut self, span: Option<Span>) {
+        match self {
+            Error::Tokenizer(error) => error.replace_spans(span),
+            Error::UnexpectedToken(token) => token.span = span,
             Error::EndOfStream => (),
         }
     }
@@ -103,6 +240,12 @@ impl From<tokenizer::Error> for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Error::Tokenizer(
+                error @ (tokenizer::Error::EmptyPlaceholder(_)
+                | tokenizer::Error::Format { .. }),
+            ) => {
+                write!(f, "{}", error)?;
+            }
             Error::Tokenizer(error) => {
                 write!(f, "Tokenizer error:\n{:?}", error)?;
             }
@@ -1,8 +1,9 @@
-use std::io;
+use std::{collections::VecDeque, io};
 
 use crate::{
+    format,
     pipeline::reader,
-    source::{Char, Span, Token, TokenKind},
+    source::{Char, Position, Span, Token, TokenKind},
 };
 
 use super::Reader;
@@ -10,11 +11,20 @@ use super::Reader;
 pub struct Tokenizer<R> {
     reader: Reader<R>,
     stream: String,
+
+    /// Tokens that have been produced, but not returned yet
+    ///
+    /// Interpolated strings produce multiple tokens at once.
+    pending: VecDeque<Token>,
 }
 
 impl<R> Tokenizer<R> {
     pub fn new(reader: Reader<R>, stream: String) -> Self {
-        Self { reader, stream }
+        Self {
+            reader,
+            stream,
+            pending: VecDeque::new(),
+        }
     }
 }
 
@@ -23,8 +33,13 @@ where
     R: io::Read,
 {
     pub fn next_token(&mut self, source: &mut String) -> Result<Token, Error> {
+        if let Some(token) = self.pending.pop_front() {
+            return Ok(token);
+        }
+
         let mut state = State::Initial;
         let mut builder = TokenBuilder::new(self.stream.clone());
+        let mut interpolated = Vec::new();
 
         loop {
             let c = self.reader.next_char(source)?;
@@ -42,6 +57,10 @@ where
                         state = State::Symbol;
                         builder.process(c);
                     }
+                    '$' => {
+                        state = State::Dollar;
+                        builder.store(c);
+                    }
                     _ => {
                         if !c.is_whitespace() {
                             state = State::Word;
@@ -72,6 +91,58 @@ where
                         builder.store(Char { c: '\n', ..c });
                         state = State::String;
                     }
+                    '"' | '\\' => {
+                        builder.store(c);
+                        state = State::String;
+                    }
+                    c => {
+                        return Err(Error::UnexpectedEscapeCode(c));
+                    }
+                },
+                State::Dollar => {
+                    if c == '"' {
+                        state = State::Interpolated;
+                        builder.process(c);
+                        continue;
+                    }
+                    if c.is_whitespace() {
+                        return Ok(builder.into_word());
+                    }
+
+                    state = State::Word;
+                    builder.store(c);
+                }
+                State::Interpolated => match c.c {
+                    '\\' => {
+                        state = State::InterpolatedEscape;
+                        builder.process(c);
+                    }
+                    '"' => {
+                        builder.process(c);
+
+                        let mut tokens =
+                            self.interpolate(interpolated, builder.span)?;
+                        let token = tokens.pop_front().unwrap();
+                        self.pending = tokens;
+
+                        return Ok(token);
+                    }
+                    _ => {
+                        builder.process(c);
+                        interpolated.push(c);
+                    }
+                },
+                State::InterpolatedEscape => match c.c {
+                    'n' => {
+                        builder.process(c);
+                        interpolated.push(Char { c: '\n', ..c });
+                        state = State::Interpolated;
+                    }
+                    '"' | '\\' => {
+                        builder.process(c);
+                        interpolated.push(c);
+                        state = State::Interpolated;
+                    }
                     c => {
                         return Err(Error::UnexpectedEscapeCode(c));
                     }
@@ -95,9 +166,147 @@ where
     }
 }
 
+impl<R> Tokenizer<R> {
+    /// Turn the characters of an interpolated string into tokens
+    ///
+    /// Each placeholder can contain words, whose tokens are emitted first. The
+    /// string itself becomes a template for `format`, which is called on the
+    /// results of the words. `$"{count} items in {name:?}"` is equivalent to
+    /// `count name "{} items in {:?}" format`.
+    fn interpolate(
+        &self,
+        chars: Vec<Char>,
+        span: Option<Span>,
+    ) -> Result<VecDeque<Token>, Error> {
+        let mut tokens = VecDeque::new();
+
+        // The position of each character in the template is tracked, so
+        // errors in the template can point to the right place.
+        let mut template = String::new();
+        let mut positions: Vec<Position> = Vec::new();
+        let mut push = |c: Char| {
+            template.push(c.c);
+            positions.push(c.pos);
+        };
+
+        let mut chars = chars.into_iter().peekable();
+        while let Some(c) = chars.next() {
+            if c != '{' {
+                push(c);
+                continue;
+            }
+            if let Some(next) = chars.next_if(|next| *next == '{') {
+                push(c);
+                push(next);
+                continue;
+            }
+
+            let mut placeholder = Vec::new();
+            let close = loop {
+                match chars.next() {
+                    Some(c) if c == '}' => break Some(c),
+                    Some(c) => placeholder.push(c),
+                    None => break None,
+                }
+            };
+
+            let close = match close {
+                Some(close) => close,
+                None => {
+                    // Leave it to the template parser to report this.
+                    push(c);
+                    placeholder.into_iter().for_each(&mut push);
+                    break;
+                }
+            };
+
+            let (words, spec) = placeholder.split_at(spec_start(&placeholder));
+            let words: Vec<_> = words
+                .split(|c| c.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .collect();
+
+            // Values from the stack would end up below the results of all
+            // other placeholders, which is confusing. Only `format` takes
+            // values from the stack.
+            if words.is_empty() {
+                return Err(Error::EmptyPlaceholder(Some(Span {
+                    stream_name: self.stream.clone(),
+                    start: c.pos,
+                    end: close.pos,
+                })));
+            }
+
+            for word in words {
+                tokens.push_back(self.word(word));
+            }
+
+            push(c);
+            spec.iter().copied().for_each(&mut push);
+            push(close);
+        }
+
+        if let Err(error) = format::Template::parse(&template) {
+            let span = Span {
+                stream_name: self.stream.clone(),
+                start: positions[error.range.start],
+                end: positions[error.range.end - 1],
+            };
+            return Err(Error::Format {
+                error,
+                span: Some(span),
+            });
+        }
+
+        tokens.push_back(Token {
+            kind: TokenKind::String(template),
+            span: span.clone(),
+        });
+        tokens.push_back(Token {
+            kind: TokenKind::Word(String::from("format")),
+            span,
+        });
+
+        Ok(tokens)
+    }
+
+    fn word(&self, chars: &[Char]) -> Token {
+        let mut builder = TokenBuilder::new(self.stream.clone());
+
+        match chars.split_first() {
+            Some((&colon, symbol)) if colon == ':' => {
+                builder.process(colon);
+                symbol.iter().for_each(|&c| builder.store(c));
+                builder.into_symbol()
+            }
+            _ => {
+                chars.iter().for_each(|&c| builder.store(c));
+                builder.into_word()
+            }
+        }
+    }
+}
+
+/// Find where the format spec starts within a placeholder
+///
+/// The spec starts with a colon, but so do symbols. A colon only starts the
+/// spec, if it directly follows a word, or starts the placeholder.
+fn spec_start(placeholder: &[Char]) -> usize {
+    (0..placeholder.len())
+        .rev()
+        .find(|&i| {
+            placeholder[i] == ':'
+                && (i == 0 || !placeholder[i - 1].is_whitespace())
+        })
+        .unwrap_or(placeholder.len())
+}
+
 enum State {
     Initial,
     Comment,
+    Dollar,
+    Interpolated,
+    InterpolatedEscape,
     String,
     StringEscape,
     Symbol,
@@ -145,8 +354,14 @@ impl TokenBuilder {
     }
 
     fn into_symbol(self) -> Token {
+        let kind = match self.buffer.as_str() {
+            "]" => TokenKind::AnnotationClose,
+
+            _ => TokenKind::Symbol(self.buffer),
+        };
+
         Token {
-            kind: TokenKind::Symbol(self.buffer),
+            kind,
             span: self.span,
         }
     }
@@ -155,6 +370,9 @@ impl TokenBuilder {
         let kind = match self.buffer.as_str() {
             "[" => TokenKind::ListOpen,
             "]" => TokenKind::ListClose,
+            "[:" => TokenKind::AnnotationOpen,
+            "(" => TokenKind::EffectOpen,
+            ")" => TokenKind::EffectClose,
 
             _ => TokenKind::parse_word(self.buffer),
         };
@@ -173,4 +391,33 @@ pub enum Error {
 
     #[error("Unexpected escape code: {0}")]
     UnexpectedEscapeCode(char),
+
+    #[error("Placeholder in interpolated string has no words")]
+    EmptyPlaceholder(Option<Span>),
+
+    #[error("Invalid interpolated string: {error}")]
+    Format {
+        error: format::Error,
+        span: Option<Span>,
+    },
+}
+
+impl Error {
+    pub fn spans<'r>(&'r self, spans: &mut Vec<&'r Span>) {
+        match self {
+            Error::EmptyPlaceholder(span) | Error::Format { span, .. } => {
+                spans.extend(span.as_ref())
+            }
+            Error::Reader(_) | Error::UnexpectedEscapeCode(_) => (),
+        }
+    }
+
+    pub fn replace_spans(&mut self, span: Option<Span>) {
+        match self {
+            Error::EmptyPlaceholder(old) | Error::Format { span: old, .. } => {
+                *old = span
+            }
+            Error::Reader(_) | Error::UnexpectedEscapeCode(_) => (),
+        }
+    }
 }
@@ -2,11 +2,13 @@ use decorum::R32;
 
 use crate::source::{Span, Token, TokenKind};
 
+#[derive(Clone, Debug)]
 pub struct Expression {
     pub kind: ExpressionKind,
     pub span: Option<Span>,
 }
 
+#[derive(Clone, Debug)]
 pub enum ExpressionKind {
     Bool(bool),
     Float(R32),
@@ -27,6 +29,10 @@ impl Expression {
             TokenKind::Symbol(value) => ExpressionKind::Symbol(value),
             TokenKind::Word(value) => ExpressionKind::Word(value),
 
+            // Parentheses that don't delimit a stack effect are words.
+            TokenKind::EffectOpen => ExpressionKind::Word("(".into()),
+            TokenKind::EffectClose => ExpressionKind::Word(")".into()),
+
             kind => panic!("Can convert {} to value", kind),
         };
 
@@ -3,7 +3,7 @@ use crate::source::Position;
 /// A location in the source code
 ///
 /// Used to identify where tokens, values, etc. originate in the source code.
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Span {
     /// The stream this source refers to
     pub stream_name: String,
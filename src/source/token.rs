@@ -14,6 +14,10 @@ pub struct Token {
 pub enum TokenKind {
     ListOpen,
     ListClose,
+    AnnotationOpen,
+    AnnotationClose,
+    EffectOpen,
+    EffectClose,
     Bool(bool),
     Float(R32),
     Number(u32),
@@ -43,6 +47,10 @@ impl fmt::Display for TokenKind {
         match self {
             TokenKind::ListOpen => write!(f, "["),
             TokenKind::ListClose => write!(f, "]"),
+            TokenKind::AnnotationOpen => write!(f, "[:"),
+            TokenKind::AnnotationClose => write!(f, ":]"),
+            TokenKind::EffectOpen => write!(f, "("),
+            TokenKind::EffectClose => write!(f, ")"),
             TokenKind::Bool(value) => value.fmt(f),
             TokenKind::Float(value) => write!(f, "{:?}", value),
             TokenKind::Number(value) => value.fmt(f),
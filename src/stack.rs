@@ -30,6 +30,14 @@ impl Stack {
         self.substacks.iter().flatten().rev()
     }
 
+    pub fn len(&self) -> usize {
+        self.substacks.iter().map(|substack| substack.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn push_raw(&mut self, value: value::Any) {
         let stack = self.substacks.last_mut().unwrap();
         stack.push(value)
@@ -45,6 +53,28 @@ impl Stack {
         None
     }
 
+    /// Remove the value at the given position, counted from the top
+    pub fn remove(&mut self, mut n: usize) -> Option<value::Any> {
+        for stack in self.substacks.iter_mut().rev() {
+            if n < stack.len() {
+                return Some(stack.remove(stack.len() - 1 - n));
+            }
+            n -= stack.len();
+        }
+
+        None
+    }
+
+    /// Remove all values
+    ///
+    /// Substacks are emptied, but not destroyed, so whoever created them can
+    /// still destroy them.
+    pub fn clear(&mut self) {
+        for stack in &mut self.substacks {
+            stack.clear();
+        }
+    }
+
     pub fn create_substack(&mut self) {
         self.substacks.push(Vec::new());
     }
@@ -53,6 +83,30 @@ impl Stack {
         self.substacks.pop().unwrap()
     }
 
+    /// Returns the current depth of the stack, so it can be restored later
+    pub fn depth(&self) -> Depth {
+        Depth {
+            lengths: self.substacks.iter().map(|stack| stack.len()).collect(),
+        }
+    }
+
+    /// Restore the stack to a depth previously returned by [`Stack::depth`]
+    ///
+    /// Removes all substacks and values that have been added since. Substacks
+    /// that have been destroyed since are created again, empty, so whoever
+    /// created them can still destroy them. Values that have been removed
+    /// since can't be restored.
+    pub fn restore(&mut self, depth: Depth) {
+        self.substacks.truncate(depth.lengths.len().max(1));
+        while self.substacks.len() < depth.lengths.len() {
+            self.create_substack();
+        }
+
+        for (stack, &len) in self.substacks.iter_mut().zip(&depth.lengths) {
+            stack.truncate(len);
+        }
+    }
+
     pub fn into_vec(mut self) -> Vec<value::Any> {
         let mut vec = Vec::new();
 
@@ -64,6 +118,13 @@ impl Stack {
     }
 }
 
+/// The depth of a [`Stack`] at some point in time
+#[derive(Clone, Debug)]
+pub struct Depth {
+    /// The number of values in each substack
+    lengths: Vec<usize>,
+}
+
 impl Default for Stack {
     fn default() -> Self {
         Self::new()
@@ -159,3 +220,42 @@ impl fmt::Display for Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::value::{self, v, Value as _};
+
+    use super::Stack;
+
+    fn values(stack: &Stack) -> Vec<Vec<value::Kind>> {
+        stack
+            .substacks
+            .iter()
+            .map(|stack| stack.iter().map(|value| value.kind.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn restore_should_handle_substacks_destroyed_since() {
+        let mut stack = Stack::new();
+        stack.push(v::Number::new(1, None));
+        stack.push(v::Number::new(2, None));
+        stack.create_substack();
+        stack.push(v::Number::new(3, None));
+
+        let depth = stack.depth();
+
+        stack.destroy_substack();
+        stack.push(v::Number::new(4, None));
+        stack.create_substack();
+        stack.create_substack();
+        stack.push(v::Number::new(5, None));
+
+        stack.restore(depth);
+
+        assert_eq!(
+            values(&stack),
+            vec![vec![value::Kind::Number(1), value::Kind::Number(2)], vec![],]
+        );
+    }
+}
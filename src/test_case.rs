@@ -0,0 +1,61 @@
+use crate::{interpreter::error::Error, source::Span, value::v};
+
+/// A named test case, registered by a program using `test`
+#[derive(Clone, Debug)]
+pub struct TestCase {
+    pub name: String,
+    pub body: v::List,
+
+    /// The location of the `test` that registered the test case
+    pub span: Option<Span>,
+}
+
+/// The result of running a [`TestCase`]
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub span: Option<Span>,
+    pub result: Result<(), Error>,
+}
+
+/// Selects tests by a string that is part of their path or name
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Filter<'r> {
+    filter: Option<&'r str>,
+}
+
+impl<'r> Filter<'r> {
+    /// Create a filter that selects everything, if `filter` is `None`
+    pub fn new(filter: Option<&'r str>) -> Self {
+        Self { filter }
+    }
+
+    /// Whether the test file at this path is selected as a whole
+    pub fn matches_path(&self, path: &str) -> bool {
+        self.filter.is_none_or(|filter| path.contains(filter))
+    }
+
+    /// Whether a test case that is registered by the test file at this path
+    /// is selected
+    pub fn matches_case(&self, path: &str, name: &str) -> bool {
+        self.matches_path(path)
+            || self.filter.is_none_or(|filter| name.contains(filter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+
+    #[test]
+    fn filters_should_select_test_cases_by_path_or_name() {
+        let filter = Filter::new(Some("sub"));
+        assert!(filter.matches_case("kr/tests/math.kr", "subtracts"));
+        assert!(!filter.matches_case("kr/tests/math.kr", "adds"));
+        assert!(filter.matches_case("kr/tests/sub.kr", "adds"));
+
+        let filter = Filter::new(None);
+        assert!(filter.matches_path("kr/tests/math.kr"));
+        assert!(filter.matches_case("kr/tests/math.kr", "adds"));
+    }
+}
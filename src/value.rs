@@ -8,7 +8,7 @@ use decorum::R32;
 
 use crate::{
     functions::Scope as Scope_,
-    source::{Expression, ExpressionKind, Span},
+    source::{Expression, ExpressionKind, Span, TokenKind},
 };
 
 pub trait Value: Sized {
@@ -90,6 +90,7 @@ macro_rules! kinds {
 
             use super::{
                 Kind,
+                ErrorInner,
                 ListInner,
                 Value,
             };
@@ -170,6 +171,16 @@ macro_rules! kinds {
             pub use types::Any;
 
 
+            /// Find a type by its name, like `number`
+            pub fn from_name(name: &str) -> Option<&'static dyn Type> {
+                match name {
+                    "any" => Some(&Any),
+                    $($name => Some(&$ty),)*
+                    _ => None,
+                }
+            }
+
+
             impl Typed for value::Any {
                 fn get_type(&self) -> &'static dyn Type {
                     match self.kind {
@@ -202,7 +213,7 @@ macro_rules! kinds {
                                 Err(
                                     TypeError {
                                         expected: self.name(),
-                                        actual:   any,
+                                        actual:   Box::new(any),
                                     }
                                 )
                             }
@@ -216,6 +227,7 @@ macro_rules! kinds {
 
 kinds!(
     Bool,   "bool",   bool;
+    Error,  "error",  Box<ErrorInner>;
     Float,  "float",  R32;
     Number, "number", u32;
     List,   "list",   ListInner;
@@ -230,6 +242,21 @@ impl PartialEq for Kind {
         // Determines equality based on data, ignoring spans.
         match (self, other) {
             (Kind::Bool(a), Kind::Bool(b)) => a == b,
+            (Kind::Error(a), Kind::Error(b)) => {
+                if a.kind != b.kind || a.fields.len() != b.fields.len() {
+                    return false;
+                }
+
+                for ((a_name, a), (b_name, b)) in
+                    a.fields.iter().zip(b.fields.iter())
+                {
+                    if a_name != b_name || a.kind != b.kind {
+                        return false;
+                    }
+                }
+
+                true
+            }
             (Kind::Float(a), Kind::Float(b)) => a == b,
             (Kind::Number(a), Kind::Number(b)) => a == b,
             (Kind::String(a), Kind::String(b)) => a == b,
@@ -273,7 +300,8 @@ impl fmt::Display for Kind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Kind::Bool(value) => value.fmt(f),
-            Kind::Float(value) => write!(f, "{:?}", value),
+            Kind::Error(value) => value.fmt(f),
+            Kind::Float(value) => write!(f, "{:?}", value.into_inner()),
             Kind::Number(value) => value.fmt(f),
             Kind::List(value) => fmt_list(&value.items, f),
             Kind::Scope(value) => write!(f, "{:?}", value),
@@ -284,6 +312,97 @@ impl fmt::Display for Kind {
     }
 }
 
+impl Kind {
+    /// Kari source code that evaluates to this value
+    ///
+    /// Lists are represented by their items, which evaluate to themselves, if
+    /// the list is parsed, but not evaluated. Returns `None` for values that
+    /// wouldn't read back as the same value: scopes, symbols and words that
+    /// couldn't have been written in source code, and errors within lists, as
+    /// they can only be created by evaluating code.
+    pub fn repr(&self) -> Option<String_> {
+        let repr = match self {
+            Kind::Error(value) => {
+                let mut repr = format!(":{} [ ", repr_symbol(&value.kind)?);
+                for (name, value) in &value.fields {
+                    repr.push_str(&format!(
+                        ":{} {} ",
+                        repr_symbol(name)?,
+                        value.kind.repr_item()?,
+                    ));
+                }
+                repr.push_str("] error");
+                repr
+            }
+            kind => kind.repr_item()?,
+        };
+
+        Some(repr)
+    }
+
+    /// Kari source code that reads back as this value, as an item of a list
+    fn repr_item(&self) -> Option<String_> {
+        let repr = match self {
+            Kind::Bool(value) => value.to_string(),
+            Kind::Error(_) => return None,
+            Kind::Float(value) => format!("{:?}", value.into_inner()),
+            Kind::Number(value) => value.to_string(),
+            Kind::List(value) => {
+                let mut repr = String_::from("[ ");
+                for item in &value.items {
+                    repr.push_str(&item.kind.repr_item()?);
+                    repr.push(' ');
+                }
+                repr.push(']');
+                repr
+            }
+            Kind::Scope(_) => return None,
+            Kind::String(value) => {
+                let escaped = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                format!("\"{}\"", escaped)
+            }
+            Kind::Symbol(value) => format!(":{}", repr_symbol(value)?),
+            Kind::Word(value) => repr_word(value)?.into(),
+        };
+
+        Some(repr)
+    }
+}
+
+/// The name of a symbol, if it can be written in source code
+fn repr_symbol(name: &str) -> Option<&str> {
+    // `:]` closes a stack annotation.
+    if name.is_empty() || name.contains(char::is_whitespace) || name == "]" {
+        return None;
+    }
+
+    Some(name)
+}
+
+/// The name of a word, if it can be written in source code
+fn repr_word(name: &str) -> Option<&str> {
+    if name.contains(char::is_whitespace) {
+        return None;
+    }
+
+    // Comments, strings, symbols, and interpolated strings.
+    if name.starts_with(['#', '"', ':']) || name.starts_with("$\"") {
+        return None;
+    }
+
+    // Delimiters, and words that read back as other values, like numbers.
+    if matches!(name, "[" | "]" | "[:" | "(" | ")") {
+        return None;
+    }
+    match TokenKind::parse_word(name.into()) {
+        TokenKind::Word(_) if !name.is_empty() => Some(name),
+        _ => None,
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ListInner {
     pub items: Vec<Any>,
@@ -311,6 +430,35 @@ impl ListInner {
     }
 }
 
+/// A structured error, created in Kari code using `error`
+///
+/// Errors have a kind, which identifies what went wrong, and any number of
+/// named fields that provide details.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ErrorInner {
+    pub kind: String_,
+    pub fields: Vec<(String_, Any)>,
+}
+
+impl ErrorInner {
+    pub fn field(&self, name: &str) -> Option<&Any> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl fmt::Display for ErrorInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ":{} [ ", self.kind)?;
+        for (name, value) in &self.fields {
+            write!(f, ":{} {} ", name, value.kind)?;
+        }
+        write!(f, "] error")
+    }
+}
+
 impl IntoIterator for v::List {
     type Item = <Vec<Any> as IntoIterator>::Item;
     type IntoIter = <Vec<Any> as IntoIterator>::IntoIter;
@@ -335,3 +483,23 @@ fn fmt_list(list: &Vec<Any>, f: &mut fmt::Formatter) -> fmt::Result {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Kind;
+
+    #[test]
+    fn repr_should_reject_names_that_read_back_as_something_else() {
+        let word = |name: &str| Kind::Word(name.into()).repr();
+        let symbol = |name: &str| Kind::Symbol(name.into()).repr();
+
+        for name in ["[", "]", "[:", "(", ")", "1", "2.5", "true", "#a", ":a"] {
+            assert_eq!(word(name), None, "{}", name);
+        }
+        assert_eq!(symbol("]"), None);
+
+        assert_eq!(word("a"), Some("a".into()));
+        assert_eq!(word(":]"), None);
+        assert_eq!(symbol(")"), Some(":)".into()));
+    }
+}
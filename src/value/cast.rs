@@ -66,7 +66,7 @@ where
 #[derive(Debug, Eq, PartialEq)]
 pub struct TypeError {
     pub expected: &'static str,
-    pub actual: value::Any,
+    pub actual: Box<value::Any>,
 }
 
 impl TypeError {
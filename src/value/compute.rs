@@ -44,3 +44,26 @@ where
         Out::new(f((a_inner, b_inner)), a_span.merge(b_span))
     }
 }
+
+impl<A, B, C> Compute for (A, B, C)
+where
+    A: Value,
+    B: Value,
+    C: Value,
+{
+    type In = (A::Inner, B::Inner, C::Inner);
+
+    fn compute<Out, F, R>(self, f: F) -> Out
+    where
+        Out: Value<Inner = R>,
+        F: FnOnce(Self::In) -> R,
+    {
+        let (a_inner, a_span) = self.0.open();
+        let (b_inner, b_span) = self.1.open();
+        let (c_inner, c_span) = self.2.open();
+        Out::new(
+            f((a_inner, b_inner, c_inner)),
+            a_span.merge(b_span).merge(c_span),
+        )
+    }
+}